@@ -0,0 +1,1419 @@
+use std::collections::{ HashMap, HashSet };
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+use solana_sdk::{
+    pubkey::{ ParsePubkeyError, Pubkey },
+    reward_type::RewardType,
+    transaction::VersionedTransaction,
+};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer,
+    EncodedConfirmedBlock,
+    EncodedTransaction,
+    EncodedTransactionWithStatusMeta,
+    TransactionBinaryEncoding,
+    UiInstruction,
+    UiTransactionStatusMeta,
+};
+
+use crate::error::{ current_slot, TpsError };
+
+//Which user transactions count toward the report, by the programs they invoke
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramFilter {
+    //When not empty, only user transactions invoking at least one of these programs count
+    pub include_only: Vec<Pubkey>,
+    //User transactions invoking any of these programs don't count, even if they invoke an included one
+    pub exclude: Vec<Pubkey>,
+}
+
+impl ProgramFilter {
+    pub fn allows(&self, program_ids: &[Pubkey]) -> bool {
+        let included = self.include_only.is_empty() ||
+            program_ids.iter().any(|program_id| self.include_only.contains(program_id));
+        let excluded = program_ids.iter().any(|program_id| self.exclude.contains(program_id));
+
+        included && !excluded
+    }
+}
+
+//Instruction to count wherever a user transaction calls it: the program and the bytes its data starts with,
+//like the discriminator of an Anchor instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountedInstruction {
+    pub program_id: Pubkey,
+    pub discriminator: Vec<u8>,
+}
+
+impl CountedInstruction {
+    //Name the instruction's count is reported under, as it was given on the command line
+    pub fn label(&self) -> String {
+        let discriminator: String = self.discriminator.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{}:{}", self.program_id, discriminator)
+    }
+}
+
+//Function that parses an instruction to count given as program:hex-discriminator, e.g. a program id followed
+//by the 8 bytes of an Anchor discriminator
+pub fn parse_counted_instruction(input: &str) -> Result<CountedInstruction, TpsError> {
+    let invalid = |reason: &str| TpsError::InvalidInstruction {
+        input: input.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let (program_id, discriminator) = input.split_once(':').ok_or_else(|| invalid("there is no ':'"))?;
+    let discriminator = discriminator.trim_start_matches("0x");
+    //Checked before slicing the digits in pairs, which only falls on character boundaries for ASCII
+    if !discriminator.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(invalid("the discriminator isn't hex"));
+    }
+    if discriminator.is_empty() || discriminator.len() % 2 != 0 {
+        return Err(invalid("the discriminator needs an even, non-zero number of hex digits"));
+    }
+
+    let discriminator = (0..discriminator.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&discriminator[index..index + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalid("the discriminator isn't hex"))?;
+
+    Ok(CountedInstruction {
+        program_id: parse_pubkey(program_id)?,
+        discriminator,
+    })
+}
+
+//Function that parses a base58 pubkey given on the command line or in a file, explaining what is wrong with it
+pub fn parse_pubkey(input: &str) -> Result<Pubkey, TpsError> {
+    Pubkey::from_str(input).map_err(|err| {
+        let reason = match err {
+            ParsePubkeyError::WrongSize => "it doesn't decode to the 32 bytes of a pubkey".to_string(),
+            ParsePubkeyError::Invalid => "it contains characters outside the base58 alphabet".to_string(),
+        };
+
+        TpsError::InvalidPubkey {
+            input: input.to_string(),
+            reason,
+        }
+    })
+}
+
+//Function that reads a file listing program ids, one per line, for a basket of programs counted together.
+//Blank lines and lines starting with # are skipped
+pub fn read_program_allowlist(path: &Path) -> Result<Vec<Pubkey>, TpsError> {
+    let content = fs::read_to_string(path)?;
+    let mut program_ids = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let program_id = parse_pubkey(line).map_err(|err| TpsError::InvalidAllowlist {
+            line: index + 1,
+            reason: err.to_string(),
+        })?;
+        program_ids.push(program_id);
+    }
+
+    Ok(program_ids)
+}
+
+//Settings that change how each block is analyzed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalysisOptions {
+    //Also count instructions invoked by programs, not only those listed in the transaction itself
+    pub include_inner: bool,
+    //Count user transactions calling this program per instruction discriminator
+    pub discriminator_program: Option<Pubkey>,
+    //Restricts the user transactions that are counted
+    pub program_filter: ProgramFilter,
+    //Programs that keep the cluster running rather than serve users. Transactions only calling these count with
+    //the vote transactions, never as user transactions
+    pub infrastructure_programs: Vec<Pubkey>,
+    //Look out for system program transfers of at least this many lamports
+    pub large_transfer_threshold: Option<u64>,
+    //Specific instructions to count, each a program and the leading bytes of its instruction data
+    pub counted_instructions: Vec<CountedInstruction>,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            include_inner: false,
+            discriminator_program: None,
+            program_filter: ProgramFilter::default(),
+            infrastructure_programs: vec![solana_sdk::vote::program::id()],
+            large_transfer_threshold: None,
+            counted_instructions: Vec::new(),
+        }
+    }
+}
+
+//Most large transfers a window keeps the signatures of. Only the largest are kept, the rest are just counted
+pub const MAX_LARGE_TRANSFERS: usize = 20;
+
+//System program transfer of at least the large transfer threshold, with the transaction it was made in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct LargeTransfer {
+    pub signature: String,
+    pub lamports: u64,
+}
+
+//Function that keeps only the largest transfers, up to the most a window keeps, largest first
+fn keep_largest_transfers(transfers: &mut Vec<LargeTransfer>) {
+    transfers.sort_by(|a, b| b.lamports.cmp(&a.lamports).then_with(|| a.signature.cmp(&b.signature)));
+    transfers.truncate(MAX_LARGE_TRANSFERS);
+}
+
+//Lamports paid out as block rewards, by reward type. Rewards can be negative, e.g. rent collected from accounts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct RewardTotals {
+    pub fee: i64,
+    pub rent: i64,
+    pub staking: i64,
+    pub voting: i64,
+    //Rewards the endpoint didn't give a type for
+    pub unknown: i64,
+}
+
+impl RewardTotals {
+    pub fn add(&mut self, other: &RewardTotals) {
+        self.fee = self.fee.saturating_add(other.fee);
+        self.rent = self.rent.saturating_add(other.rent);
+        self.staking = self.staking.saturating_add(other.staking);
+        self.voting = self.voting.saturating_add(other.voting);
+        self.unknown = self.unknown.saturating_add(other.unknown);
+    }
+
+    pub fn total(&self) -> i64 {
+        self.fee
+            .saturating_add(self.rent)
+            .saturating_add(self.staking)
+            .saturating_add(self.voting)
+            .saturating_add(self.unknown)
+    }
+}
+
+//Sizes a streaming scan keeps a sample of to estimate the size percentiles from
+pub const STREAMING_SIZE_SAMPLE: usize = 10_000;
+
+//Distribution of the serialized sizes of user transactions, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct SizeDistribution {
+    pub average: f64,
+    pub std_dev: f64,
+    pub p50: u64,
+    pub p99: u64,
+    pub max: u64,
+    //Whether p50 and p99 were estimated from a random sample of the sizes, as streaming scans do. The average,
+    //standard deviation and max are exact either way
+    pub approximate: bool,
+}
+
+//Running statistics over transaction sizes. The variance is kept with Welford's algorithm, so it doesn't need
+//the sizes, and the percentiles come from a reservoir sample when the sizes kept are limited
+#[derive(Debug, Clone)]
+pub struct SizeStats {
+    count: u64,
+    total: u64,
+    mean: f64,
+    m2: f64,
+    max: u64,
+    sample: Vec<u64>,
+    sample_limit: Option<usize>,
+    //State of the generator picking which sizes the sample keeps. Fixed, so a scan always keeps the same ones
+    rng_state: u64,
+}
+
+impl SizeStats {
+    //Function that creates the statistics, keeping every size or a uniform sample of at most the given number
+    pub fn new(sample_limit: Option<usize>) -> Self {
+        SizeStats {
+            count: 0,
+            total: 0,
+            mean: 0.0,
+            m2: 0.0,
+            max: 0,
+            sample: Vec::new(),
+            sample_limit,
+            rng_state: 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    pub fn add(&mut self, size: u64) {
+        self.count += 1;
+        self.total = self.total.saturating_add(size);
+        let delta = (size as f64) - self.mean;
+        self.mean += delta / (self.count as f64);
+        self.m2 += delta * ((size as f64) - self.mean);
+        self.max = self.max.max(size);
+
+        match self.sample_limit {
+            Some(limit) if self.sample.len() >= limit => {
+                //Reservoir sampling: the n-th size replaces a kept one with probability limit / n
+                let index = self.next_random() % self.count;
+                if let Some(kept) = self.sample.get_mut(index as usize) {
+                    *kept = size;
+                }
+            }
+            _ => self.sample.push(size),
+        }
+    }
+
+    //Function that returns the next number of a SplitMix64 sequence
+    fn next_random(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    //Function that summarizes the sizes seen, None when there were none. Percentiles use the nearest rank
+    pub fn distribution(&self) -> Option<SizeDistribution> {
+        if self.count == 0 {
+            return None;
+        }
+        let mut sizes = self.sample.clone();
+        sizes.sort_unstable();
+
+        let percentile = |percent: usize| {
+            let rank = (sizes.len() * percent).div_ceil(100).max(1);
+            sizes[rank - 1]
+        };
+
+        Some(SizeDistribution {
+            average: (self.total as f64) / (self.count as f64),
+            std_dev: (self.m2 / (self.count as f64)).sqrt(),
+            p50: percentile(50),
+            p99: percentile(99),
+            max: self.max,
+            approximate: (sizes.len() as u64) < self.count,
+        })
+    }
+}
+
+//Most fee payers a scan keeps counts for. Past it the least busy half is dropped, so the map stays bounded
+pub const MAX_TRACKED_SIGNERS: usize = 100_000;
+
+//User transactions per fee payer over a window, for the busiest signers. Bounded by MAX_TRACKED_SIGNERS: past
+//that many payers only the busiest half is kept, so a payer seen again afterwards starts over and its count is
+//a lower bound. Payers busy enough to be reported in practice are never dropped
+#[derive(Debug, Clone, Default)]
+pub struct SignerCounts {
+    pub counts: HashMap<Pubkey, u64>,
+    //Whether payers were ever dropped, which makes the counts lower bounds
+    pub pruned: bool,
+}
+
+impl SignerCounts {
+    pub fn add(&mut self, fee_payer_counts: HashMap<Pubkey, u64>) {
+        for (fee_payer, count) in fee_payer_counts {
+            *self.counts.entry(fee_payer).or_default() += count;
+        }
+
+        //Pruned by rank, in the order the busiest signers are reported in, so exactly half are kept however
+        //many payers are tied and a payer that ranks among them is never dropped for a tie
+        if self.counts.len() > MAX_TRACKED_SIGNERS {
+            let mut counts: Vec<(Pubkey, u64)> = self.counts.drain().collect();
+            counts.select_nth_unstable_by(MAX_TRACKED_SIGNERS / 2, |a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            counts.truncate(MAX_TRACKED_SIGNERS / 2);
+            self.counts.extend(counts);
+            self.pruned = true;
+        }
+    }
+}
+
+//Distribution of the fees paid by user transactions, in lamports
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct FeeDistribution {
+    pub median: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl FeeDistribution {
+    //Function that summarizes the number of transactions paying each fee, None when there are none.
+    //Percentiles use the nearest rank, like the size distribution
+    pub fn from_counts(fee_counts: &HashMap<u64, u64>) -> Option<Self> {
+        let mut fees: Vec<(u64, u64)> = fee_counts.iter().map(|(fee, count)| (*fee, *count)).collect();
+        fees.sort_unstable();
+
+        let transactions: u64 = fees.iter().map(|(_, count)| count).sum();
+        if transactions == 0 {
+            return None;
+        }
+
+        let percentile = |percent: u64| {
+            let rank = (transactions * percent).div_ceil(100).max(1);
+            let mut seen = 0;
+            fees.iter()
+                .find(|(_, count)| {
+                    seen += count;
+                    seen >= rank
+                })
+                .map(|(fee, _)| *fee)
+                .expect("rank is within the counted transactions")
+        };
+
+        Some(FeeDistribution {
+            median: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: fees[fees.len() - 1].0,
+        })
+    }
+}
+
+//TPS over one stretch of a scanned window, when the window is split into buckets of a fixed length
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct TpsBucket {
+    //Block times the bucket covers, the start excluded like the window's oldest block
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+    pub transactions_per_second: f64,
+    pub user_transactions: u64,
+    pub blocks: u64,
+}
+
+//Function that splits a window into buckets of the given length and adds every counted block to the bucket
+//its block time falls in. Buckets are aligned to the window start: the first one begins at the oldest block time
+//and each is the given length, so the boundaries only depend on the window and only the newest one can be short.
+//Blocks are given as (block time, transactions counted toward TPS, user transactions), buckets come oldest first
+pub fn bucket_tps(
+    blocks: &[(i64, u64, u64)],
+    oldest_timestamp: i64,
+    newest_timestamp: i64,
+    bucket_seconds: u64
+) -> Vec<TpsBucket> {
+    let span = newest_timestamp.saturating_sub(oldest_timestamp);
+    let bucket_seconds = bucket_seconds.max(1) as i64;
+    if span <= 0 {
+        return Vec::new();
+    }
+
+    let bucket_count = ((span + bucket_seconds - 1) / bucket_seconds) as usize;
+    let mut counts = vec![(0u64, 0u64, 0u64); bucket_count];
+    for (block_time, counted_transactions, user_transactions) in blocks {
+        //Bucket starts are excluded and ends included. Block times only have second resolution, so a counted
+        //block can share the oldest block's time, and goes in the first bucket
+        let offset = block_time.saturating_sub(oldest_timestamp);
+        let index = ((offset + bucket_seconds - 1) / bucket_seconds - 1).clamp(0, bucket_count as i64 - 1);
+        let count = &mut counts[index as usize];
+        count.0 += counted_transactions;
+        count.1 += user_transactions;
+        count.2 += 1;
+    }
+
+    counts
+        .iter()
+        .enumerate()
+        .map(|(index, (counted_transactions, user_transactions, blocks))| {
+            let start_timestamp = oldest_timestamp + (index as i64) * bucket_seconds;
+            let end_timestamp = (start_timestamp + bucket_seconds).min(newest_timestamp);
+
+            TpsBucket {
+                start_timestamp,
+                end_timestamp,
+                transactions_per_second: calculate_tps(start_timestamp, end_timestamp, *counted_transactions),
+                user_transactions: *user_transactions,
+                blocks: *blocks,
+            }
+        })
+        .collect()
+}
+
+//Function that returns the serialized size, in bytes, of a transaction fetched in a binary encoding.
+//Base64 encodes every 3 bytes as 4 characters, padded with = to a multiple of 4. Other encodings return None
+pub fn serialized_transaction_size(transaction: &EncodedTransaction) -> Option<u64> {
+    match transaction {
+        EncodedTransaction::Binary(blob, TransactionBinaryEncoding::Base64) => {
+            let padding = blob.bytes().rev().take_while(|byte| *byte == b'=').count();
+            Some(((blob.len() / 4) * 3).saturating_sub(padding) as u64)
+        }
+        _ => None,
+    }
+}
+
+//Per-block numbers that a scan adds up over its window
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockAnalysis {
+    pub total_transactions: u64,
+    pub user_transactions: u64,
+    //Transactions without any instruction. They are neither vote nor user transactions
+    pub empty_transactions: u64,
+    //User transactions left out by the program filter. They count toward none of the user transaction numbers
+    pub filtered_out: u64,
+    //User transactions that executed without an error. Transactions without status metadata don't count
+    pub successful_user_transactions: u64,
+    pub fees: u64,
+    //Fees split by whether the paying transaction executed successfully. Failed transactions still pay
+    pub fees_from_successful: u64,
+    pub fees_from_failed: u64,
+    //Fees split into the base fee charged per signature and the priority fee paid on top of it
+    pub base_fees: u64,
+    pub priority_fees: u64,
+    //Number of instructions invoking each program, across all transactions in the block
+    pub instruction_counts: HashMap<Pubkey, u64>,
+    //Number of user transactions invoking each program at least once
+    pub program_transaction_counts: HashMap<Pubkey, u64>,
+    //User transactions that only call the system program, i.e. plain SOL transfers and account creation
+    pub system_program_transactions: u64,
+    //User transactions using a durable nonce instead of a recent blockhash
+    pub durable_nonce_transactions: u64,
+    //System program transfers of at least the large transfer threshold, and the largest of them
+    pub large_transfer_count: u64,
+    pub large_transfers: Vec<LargeTransfer>,
+    //Signatures the user transactions require, from their message headers, and how many require more than one
+    pub required_signatures: u64,
+    pub multisig_transactions: u64,
+    //User transactions that loaded accounts from address lookup tables
+    pub lookup_table_transactions: u64,
+    //Top-level instructions of the user transactions
+    pub total_instructions: u64,
+    //Compute units the user transactions consumed. Transactions whose metadata doesn't record it add nothing
+    pub compute_units: u64,
+    //Serialized size of every user transaction, in bytes, so the window's distribution can be computed
+    pub transaction_sizes: Vec<u64>,
+    //Number of user transactions paying each fee, in lamports. Fees cluster on a few values, so unlike the
+    //sizes this stays small however many transactions a window has
+    pub fee_counts: HashMap<u64, u64>,
+    //Number of user transactions calling the discriminator program with each instruction discriminator, keyed by hex
+    pub discriminator_counts: HashMap<String, u64>,
+    //Top-level instructions of the user transactions matching each counted instruction, keyed by its label
+    pub counted_instruction_counts: HashMap<String, u64>,
+    //Distinct fee payers among the user transactions, so a payer spamming the block counts once
+    pub distinct_fee_payers: u64,
+    //Number of user transactions paid for by each fee payer
+    pub fee_payer_counts: HashMap<Pubkey, u64>,
+    pub rewards: RewardTotals,
+    //Blocks without a single transaction that still paid out rewards, as at epoch boundaries. They were
+    //produced, so they count toward the skip rate, but add nothing to TPS
+    pub reward_only_blocks: u64,
+}
+
+impl BlockAnalysis {
+    //Adds the numbers of another block, so a single analysis can hold the totals of a whole window
+    pub fn add(&mut self, other: BlockAnalysis) {
+        self.total_transactions = self.total_transactions
+            .checked_add(other.total_transactions)
+            .expect("Overflow");
+        self.user_transactions = self.user_transactions
+            .checked_add(other.user_transactions)
+            .expect("Overflow");
+        self.empty_transactions += other.empty_transactions;
+        self.filtered_out += other.filtered_out;
+        self.successful_user_transactions += other.successful_user_transactions;
+        self.fees = self.fees.saturating_add(other.fees);
+        self.fees_from_successful = self.fees_from_successful.saturating_add(other.fees_from_successful);
+        self.fees_from_failed = self.fees_from_failed.saturating_add(other.fees_from_failed);
+        self.base_fees = self.base_fees.saturating_add(other.base_fees);
+        self.priority_fees = self.priority_fees.saturating_add(other.priority_fees);
+        for (program_id, count) in other.instruction_counts {
+            *self.instruction_counts.entry(program_id).or_default() += count;
+        }
+        for (program_id, count) in other.program_transaction_counts {
+            *self.program_transaction_counts.entry(program_id).or_default() += count;
+        }
+        self.system_program_transactions += other.system_program_transactions;
+        self.durable_nonce_transactions += other.durable_nonce_transactions;
+        self.large_transfer_count += other.large_transfer_count;
+        self.large_transfers.extend(other.large_transfers);
+        keep_largest_transfers(&mut self.large_transfers);
+        self.required_signatures += other.required_signatures;
+        self.multisig_transactions += other.multisig_transactions;
+        self.lookup_table_transactions += other.lookup_table_transactions;
+        self.total_instructions += other.total_instructions;
+        self.compute_units = self.compute_units.saturating_add(other.compute_units);
+        self.transaction_sizes.extend(other.transaction_sizes);
+        for (fee, count) in other.fee_counts {
+            *self.fee_counts.entry(fee).or_default() += count;
+        }
+        for (discriminator, count) in other.discriminator_counts {
+            *self.discriminator_counts.entry(discriminator).or_default() += count;
+        }
+        for (label, count) in other.counted_instruction_counts {
+            *self.counted_instruction_counts.entry(label).or_default() += count;
+        }
+        self.distinct_fee_payers += other.distinct_fee_payers;
+        for (fee_payer, count) in other.fee_payer_counts {
+            *self.fee_payer_counts.entry(fee_payer).or_default() += count;
+        }
+        self.rewards.add(&other.rewards);
+        self.reward_only_blocks += other.reward_only_blocks;
+    }
+}
+
+//Function that decodes every transaction in a block, paired with its status. A transaction that can't be decoded,
+//e.g. one a file holds in JSON rather than binary encoding, is left out with a warning instead of failing the block
+pub fn decode_transactions(
+    block: &EncodedConfirmedBlock
+) -> Vec<(&EncodedTransactionWithStatusMeta, VersionedTransaction)> {
+    let _span = tracing::trace_span!("decode").entered();
+    block.transactions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, transaction_status)| match transaction_status.transaction.decode() {
+            Some(transaction) => Some((transaction_status, transaction)),
+            None => {
+                match current_slot() {
+                    Some(slot) => log::warn!("Leaving out transaction {} of block {}, it can't be decoded", index, slot),
+                    None => log::warn!("Leaving out transaction {} of a block, it can't be decoded", index),
+                }
+                None
+            }
+        })
+        .collect()
+}
+
+//Function that tells whether a transaction has no instructions at all
+pub fn is_empty_transaction(transaction: &VersionedTransaction) -> bool {
+    transaction.message.instructions().is_empty()
+}
+
+//Function that tells whether every instruction of a transaction goes to one of the given infrastructure programs,
+//which makes it a non-user transaction like a vote. A transaction without instructions isn't one
+pub fn is_infrastructure_transaction(transaction: &VersionedTransaction, infrastructure_programs: &[Pubkey]) -> bool {
+    if is_empty_transaction(transaction) {
+        log::debug!("It's an empty transaction");
+        return false;
+    }
+
+    let program_ids = program_ids_in_transaction(transaction, transaction.message.static_account_keys());
+
+    let mut num_vote_instructions = 0;
+    for program_id in program_ids {
+        if infrastructure_programs.contains(&program_id) {
+            num_vote_instructions += 1;
+            log::debug!("Vote instruction found");
+        } else {
+            log::debug!("User instruction found");
+        }
+    }
+
+    if num_vote_instructions == transaction.message.instructions().len() {
+        log::debug!("It's a vote transaction");
+        true
+    } else {
+        log::debug!("It's a user transaction");
+        false
+    }
+}
+
+//Function that logs how the txns of a block split into vote txns, user txns and empty txns
+fn log_transaction_split(
+    total_transactions_count: u64,
+    user_transactions_count: u64,
+    empty_transactions_count: u64
+) {
+    let vote_transactions_count = total_transactions_count
+        .checked_sub(user_transactions_count)
+        .and_then(|count| count.checked_sub(empty_transactions_count))
+        .expect("Underflow");
+
+    log::debug!("Solana total txns: {}", total_transactions_count);
+    log::debug!("Solana user txns: {}", user_transactions_count);
+    log::debug!("Solana vote txns: {}", vote_transactions_count);
+    log::debug!("Solana empty txns: {}", empty_transactions_count);
+}
+
+//Function that tells whether a transaction's only activity is the system program, like a plain SOL transfer.
+//Compute budget instructions just set fees and limits, so they don't make a transaction a program interaction
+pub fn is_system_program_transaction(transaction: &VersionedTransaction) -> bool {
+    let mut program_ids = program_ids_in_transaction(transaction, transaction.message.static_account_keys())
+        .into_iter()
+        .filter(|program_id| *program_id != solana_sdk::compute_budget::id())
+        .peekable();
+
+    program_ids.peek().is_some() &&
+        program_ids.all(|program_id| program_id == solana_sdk::system_program::id())
+}
+
+//Index of AdvanceNonceAccount among the system program's instructions, the first four bytes of its data
+const ADVANCE_NONCE_ACCOUNT_DISCRIMINATOR: [u8; 4] = 4u32.to_le_bytes();
+
+//Function that tells whether a transaction uses a durable nonce, which it does by advancing the nonce
+//account in its first instruction
+pub fn is_durable_nonce_transaction(transaction: &VersionedTransaction) -> bool {
+    let account_keys = transaction.message.static_account_keys();
+
+    transaction.message
+        .instructions()
+        .first()
+        .is_some_and(|instruction| {
+            account_keys.get(usize::from(instruction.program_id_index)) == Some(&solana_sdk::system_program::id()) &&
+                instruction.data.starts_with(&ADVANCE_NONCE_ACCOUNT_DISCRIMINATOR)
+        })
+}
+
+//Indexes of Transfer and TransferWithSeed among the system program's instructions. Both carry the lamports
+//they move right after the index
+const TRANSFER_DISCRIMINATOR: [u8; 4] = 2u32.to_le_bytes();
+const TRANSFER_WITH_SEED_DISCRIMINATOR: [u8; 4] = 11u32.to_le_bytes();
+
+//Function that returns the lamports moved by every top-level system program transfer of a transaction.
+//Transfers made by other programs through inner instructions aren't seen
+pub fn system_transfer_amounts(transaction: &VersionedTransaction) -> Vec<u64> {
+    let account_keys = transaction.message.static_account_keys();
+
+    transaction.message
+        .instructions()
+        .iter()
+        .filter(|instruction| {
+            account_keys.get(usize::from(instruction.program_id_index)) == Some(&solana_sdk::system_program::id())
+        })
+        .filter(|instruction| {
+            instruction.data.starts_with(&TRANSFER_DISCRIMINATOR) ||
+                instruction.data.starts_with(&TRANSFER_WITH_SEED_DISCRIMINATOR)
+        })
+        .filter_map(|instruction| instruction.data.get(4..12))
+        .map(|lamports| u64::from_le_bytes(lamports.try_into().expect("slice is 8 bytes")))
+        .collect()
+}
+
+//Function that tells whether a transaction loaded any accounts from address lookup tables, which only v0
+//transactions can. Without status metadata there is no telling, so it counts as not using them
+pub fn uses_lookup_tables(meta: Option<&UiTransactionStatusMeta>) -> bool {
+    meta.is_some_and(|meta| match meta.loaded_addresses.as_ref() {
+        OptionSerializer::Some(loaded_addresses) => {
+            !loaded_addresses.writable.is_empty() || !loaded_addresses.readonly.is_empty()
+        }
+        _ => false,
+    })
+}
+
+//Function that returns the compute units a transaction consumed, when its status metadata records them.
+//Nodes only started recording them in 1.13, older blocks come without
+pub fn compute_units_consumed(meta: Option<&UiTransactionStatusMeta>) -> Option<u64> {
+    match meta?.compute_units_consumed {
+        OptionSerializer::Some(compute_units) => Some(compute_units),
+        _ => None,
+    }
+}
+
+//Function that returns the program id of each top-level instruction of a transaction, in order, looked up in
+//the given account keys. Instructions pointing past the keys, as lookup table transactions can when only their
+//static keys are given, are left out instead of panicking
+pub fn program_ids_in_transaction(transaction: &VersionedTransaction, account_keys: &[Pubkey]) -> Vec<Pubkey> {
+    transaction.message
+        .instructions()
+        .iter()
+        .filter_map(|instruction| {
+            let program_id = account_keys.get(usize::from(instruction.program_id_index));
+            if program_id.is_none() {
+                log::debug!("Instruction program index {} is out of the account keys", instruction.program_id_index);
+            }
+            program_id
+        })
+        .copied()
+        .collect()
+}
+
+//Function that returns the program invoked by each instruction of a transaction, in order.
+//Inner instructions can reference accounts loaded from address lookup tables, so they are resolved
+//against the static keys followed by the loaded writable and readonly addresses
+pub fn invoked_programs(
+    transaction: &VersionedTransaction,
+    meta: Option<&UiTransactionStatusMeta>,
+    include_inner: bool
+) -> Vec<Pubkey> {
+    let account_keys = transaction.message.static_account_keys();
+
+    let mut program_ids = program_ids_in_transaction(transaction, account_keys);
+
+    if !include_inner {
+        return program_ids;
+    }
+
+    let meta = match meta {
+        Some(meta) => meta,
+        None => {
+            return program_ids;
+        }
+    };
+
+    let inner_instructions = match meta.inner_instructions.as_ref() {
+        OptionSerializer::Some(inner_instructions) => inner_instructions,
+        _ => {
+            return program_ids;
+        }
+    };
+
+    let mut all_account_keys = account_keys.to_vec();
+    if let OptionSerializer::Some(loaded_addresses) = meta.loaded_addresses.as_ref() {
+        all_account_keys.extend(
+            loaded_addresses.writable
+                .iter()
+                .chain(loaded_addresses.readonly.iter())
+                .filter_map(|address| Pubkey::from_str(address).ok())
+        );
+    }
+
+    for instruction in inner_instructions.iter().flat_map(|inner| inner.instructions.iter()) {
+        if let UiInstruction::Compiled(instruction) = instruction {
+            let program_id = all_account_keys.get(usize::from(instruction.program_id_index));
+            if let Some(program_id) = program_id {
+                program_ids.push(*program_id);
+            }
+        }
+    }
+
+    program_ids
+}
+
+//How many leading bytes of instruction data make up a discriminator, as used by Anchor programs
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+//Function that returns the distinct discriminators, as hex, of the top-level instructions calling the program.
+//Instructions with less data than a full discriminator use all of it
+pub fn instruction_discriminators(transaction: &VersionedTransaction, program_id: &Pubkey) -> HashSet<String> {
+    let account_keys = transaction.message.static_account_keys();
+
+    transaction.message
+        .instructions()
+        .iter()
+        .filter(|instruction| account_keys.get(usize::from(instruction.program_id_index)) == Some(program_id))
+        .map(|instruction| {
+            instruction.data
+                .iter()
+                .take(DISCRIMINATOR_LEN)
+                .map(|byte| format!("{:02x}", byte))
+                .collect()
+        })
+        .collect()
+}
+
+//Function that counts the top-level instructions of a transaction that call the counted instruction
+pub fn count_matching_instructions(transaction: &VersionedTransaction, counted: &CountedInstruction) -> u64 {
+    let account_keys = transaction.message.static_account_keys();
+
+    transaction.message
+        .instructions()
+        .iter()
+        .filter(|instruction| {
+            account_keys.get(usize::from(instruction.program_id_index)) == Some(&counted.program_id) &&
+                instruction.data.starts_with(&counted.discriminator)
+        })
+        .count() as u64
+}
+
+//Function that analyzes a block, counting its transactions, fees and the programs they invoke.
+//A user transaction counts once toward every distinct program it invokes, and once toward every
+//distinct discriminator it calls the discriminator program with
+pub fn analyze_block(block: &EncodedConfirmedBlock, options: &AnalysisOptions) -> BlockAnalysis {
+    if block.transactions.is_empty() {
+        log::debug!("Block has no transactions");
+    }
+
+    let transactions = decode_transactions(block);
+
+    let _span = tracing::trace_span!("count").entered();
+    let mut user_transactions: u64 = 0;
+    let mut empty_transactions: u64 = 0;
+    let mut filtered_out: u64 = 0;
+    let mut successful_user_transactions: u64 = 0;
+    let mut system_program_transactions: u64 = 0;
+    let mut durable_nonce_transactions: u64 = 0;
+    let mut required_signatures: u64 = 0;
+    let mut multisig_transactions: u64 = 0;
+    let mut lookup_table_transactions: u64 = 0;
+    let mut large_transfer_count: u64 = 0;
+    let mut large_transfers: Vec<LargeTransfer> = Vec::new();
+    let mut total_instructions: u64 = 0;
+    let mut compute_units: u64 = 0;
+    let mut transaction_sizes: Vec<u64> = Vec::new();
+    let mut fee_counts: HashMap<u64, u64> = HashMap::new();
+    let mut instruction_counts: HashMap<Pubkey, u64> = HashMap::new();
+    let mut program_transaction_counts: HashMap<Pubkey, u64> = HashMap::new();
+    let mut discriminator_counts: HashMap<String, u64> = HashMap::new();
+    let mut counted_instruction_counts: HashMap<String, u64> = HashMap::new();
+    let mut fee_payer_counts: HashMap<Pubkey, u64> = HashMap::new();
+    let mut base_fees: u64 = 0;
+    let mut priority_fees: u64 = 0;
+
+    for (transaction_status, transaction) in &transactions {
+        if let Some(meta) = &transaction_status.meta {
+            let (base_fee, priority_fee) = split_fee(meta.fee, transaction.signatures.len());
+            base_fees = base_fees.saturating_add(base_fee);
+            priority_fees = priority_fees.saturating_add(priority_fee);
+        }
+
+        let program_ids = invoked_programs(transaction, transaction_status.meta.as_ref(), options.include_inner);
+
+        for program_id in &program_ids {
+            *instruction_counts.entry(*program_id).or_default() += 1;
+        }
+
+        if is_empty_transaction(transaction) {
+            empty_transactions += 1;
+            continue;
+        }
+
+        if is_infrastructure_transaction(transaction, &options.infrastructure_programs) {
+            continue;
+        }
+
+        if !options.program_filter.allows(&program_ids) {
+            filtered_out += 1;
+            continue;
+        }
+
+        user_transactions += 1;
+
+        //The fee payer is always the first account of the message
+        if let Some(fee_payer) = transaction.message.static_account_keys().first() {
+            *fee_payer_counts.entry(*fee_payer).or_default() += 1;
+        }
+
+        if transaction_status.meta.as_ref().is_some_and(|meta| meta.err.is_none()) {
+            successful_user_transactions += 1;
+        }
+
+        if let Some(meta) = &transaction_status.meta {
+            *fee_counts.entry(meta.fee).or_default() += 1;
+        }
+
+        if is_system_program_transaction(transaction) {
+            system_program_transactions += 1;
+        }
+
+        if is_durable_nonce_transaction(transaction) {
+            durable_nonce_transactions += 1;
+        }
+
+        if let Some(threshold) = options.large_transfer_threshold {
+            for lamports in system_transfer_amounts(transaction) {
+                if lamports >= threshold {
+                    large_transfer_count += 1;
+                    large_transfers.push(LargeTransfer {
+                        signature: transaction.signatures.first().map(ToString::to_string).unwrap_or_default(),
+                        lamports,
+                    });
+                }
+            }
+        }
+
+        let signers = u64::from(transaction.message.header().num_required_signatures);
+        required_signatures += signers;
+        if signers > 1 {
+            multisig_transactions += 1;
+        }
+
+        if uses_lookup_tables(transaction_status.meta.as_ref()) {
+            lookup_table_transactions += 1;
+        }
+
+        total_instructions += transaction.message.instructions().len() as u64;
+
+        if let Some(consumed) = compute_units_consumed(transaction_status.meta.as_ref()) {
+            compute_units = compute_units.saturating_add(consumed);
+        }
+
+        if let Some(size) = serialized_transaction_size(&transaction_status.transaction) {
+            transaction_sizes.push(size);
+        }
+
+        let distinct_program_ids: HashSet<Pubkey> = program_ids.into_iter().collect();
+        for program_id in distinct_program_ids {
+            *program_transaction_counts.entry(program_id).or_default() += 1;
+        }
+
+        if let Some(discriminator_program) = &options.discriminator_program {
+            for discriminator in instruction_discriminators(transaction, discriminator_program) {
+                *discriminator_counts.entry(discriminator).or_default() += 1;
+            }
+        }
+
+        for counted in &options.counted_instructions {
+            let matching = count_matching_instructions(transaction, counted);
+            if matching > 0 {
+                *counted_instruction_counts.entry(counted.label()).or_default() += matching;
+            }
+        }
+    }
+
+    log_transaction_split(transactions.len() as u64, user_transactions + filtered_out, empty_transactions);
+
+    let (fees_from_successful, fees_from_failed) = split_fees_by_status(block);
+    keep_largest_transfers(&mut large_transfers);
+
+    BlockAnalysis {
+        total_transactions: transactions.len() as u64,
+        user_transactions,
+        empty_transactions,
+        filtered_out,
+        successful_user_transactions,
+        fees: sum_fees(block),
+        fees_from_successful,
+        fees_from_failed,
+        base_fees,
+        priority_fees,
+        instruction_counts,
+        program_transaction_counts,
+        system_program_transactions,
+        durable_nonce_transactions,
+        large_transfer_count,
+        large_transfers,
+        required_signatures,
+        multisig_transactions,
+        lookup_table_transactions,
+        total_instructions,
+        compute_units,
+        transaction_sizes,
+        fee_counts,
+        discriminator_counts,
+        counted_instruction_counts,
+        distinct_fee_payers: fee_payer_counts.len() as u64,
+        fee_payer_counts,
+        rewards: sum_rewards(block),
+        reward_only_blocks: u64::from(is_reward_only_block(block)),
+    }
+}
+
+//Function that tells whether a block has no transactions but paid out rewards. A block fetched without its
+//rewards or its transactions is never reward-only, since there is no telling
+pub fn is_reward_only_block(block: &EncodedConfirmedBlock) -> bool {
+    block.transactions.is_empty() && !block.rewards.is_empty()
+}
+
+//Function that sums the fees, in lamports, paid by all transactions in a block
+pub fn sum_fees(block: &EncodedConfirmedBlock) -> u64 {
+    block.transactions
+        .iter()
+        .filter_map(|transaction_status| transaction_status.meta.as_ref())
+        .fold(0u64, |total, meta| total.saturating_add(meta.fee))
+}
+
+//Base fee charged per signature, in lamports
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5000;
+
+//Share of base fees that is burned rather than paid to the leader
+pub const BASE_FEE_BURN_RATE: f64 = 0.5;
+
+//Function that splits the fee a transaction paid into its base fee, set by its number of signatures,
+//and the priority fee paid on top of it
+pub fn split_fee(fee: u64, signatures: usize) -> (u64, u64) {
+    let base_fee = (signatures as u64).saturating_mul(LAMPORTS_PER_SIGNATURE).min(fee);
+
+    (base_fee, fee - base_fee)
+}
+
+//Function that estimates the lamports burned from the given base fees
+pub fn burned_fees(base_fees: u64) -> u64 {
+    ((base_fees as f64) * BASE_FEE_BURN_RATE) as u64
+}
+
+//Function that sums the rewards of a block by reward type. A block without rewards sums to zero
+pub fn sum_rewards(block: &EncodedConfirmedBlock) -> RewardTotals {
+    let mut totals = RewardTotals::default();
+
+    for reward in &block.rewards {
+        let total = match reward.reward_type {
+            Some(RewardType::Fee) => &mut totals.fee,
+            Some(RewardType::Rent) => &mut totals.rent,
+            Some(RewardType::Staking) => &mut totals.staking,
+            Some(RewardType::Voting) => &mut totals.voting,
+            None => &mut totals.unknown,
+        };
+        *total = total.saturating_add(reward.lamports);
+    }
+
+    totals
+}
+
+//Function that sums the fees, in lamports, paid by successful and by failed transactions in a block
+pub fn split_fees_by_status(block: &EncodedConfirmedBlock) -> (u64, u64) {
+    block.transactions
+        .iter()
+        .filter_map(|transaction_status| transaction_status.meta.as_ref())
+        .fold((0u64, 0u64), |(successful, failed), meta| {
+            if meta.err.is_none() {
+                (successful.saturating_add(meta.fee), failed)
+            } else {
+                (successful, failed.saturating_add(meta.fee))
+            }
+        })
+}
+
+//Function that returns the share of user transactions that landed successfully, between 0.0 and 1.0.
+//Without any user transactions there is no rate, so it returns None
+pub fn landed_rate(successful_user_transactions: u64, user_transactions: u64) -> Option<f64> {
+    if user_transactions == 0 {
+        return None;
+    }
+
+    Some((successful_user_transactions as f64) / (user_transactions as f64))
+}
+
+//Function that returns how many vote transactions there were per user transaction.
+//Without any user transactions there is no ratio, so it returns None
+pub fn vote_to_user_ratio(vote_transactions: u64, user_transactions: u64) -> Option<f64> {
+    if user_transactions == 0 {
+        return None;
+    }
+
+    Some((vote_transactions as f64) / (user_transactions as f64))
+}
+
+//How the transactions of a window are averaged into TPS
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TpsAverageMethod {
+    //Transactions divided by the seconds between the oldest and newest block time. Block times only have
+    //whole-second resolution, and a cluster that stalls between two blocks stretches the span
+    #[default]
+    Simple,
+    //Every block's transactions are spread over the slots since its parent at the nominal slot time, so the
+    //average follows the slot clock. Skipped slots still count as time, stalls in block times don't
+    Weighted,
+}
+
+impl TpsAverageMethod {
+    pub fn label(self) -> &'static str {
+        match self {
+            TpsAverageMethod::Simple => "simple",
+            TpsAverageMethod::Weighted => "time-weighted",
+        }
+    }
+
+    //Function that averages the given transactions into TPS over a window spanning the given block times and
+    //counted slots
+    pub fn tps(self, oldest_timestamp: i64, newest_timestamp: i64, transaction_count: u64, counted_slots: u64) -> f64 {
+        match self {
+            TpsAverageMethod::Simple => calculate_tps(oldest_timestamp, newest_timestamp, transaction_count),
+            TpsAverageMethod::Weighted => tps_from_slots(transaction_count, counted_slots),
+        }
+    }
+}
+
+//Nominal length of a slot, which the time-weighted average measures time in
+pub const NOMINAL_SLOT_SECONDS: f64 = 0.4;
+
+//Function that counts the number of transactions per second over the given number of slots at the nominal slot time
+pub fn tps_from_slots(transaction_count: u64, slots: u64) -> f64 {
+    tps_from_span(transaction_count, (slots as f64) * NOMINAL_SLOT_SECONDS)
+}
+
+//Fewest slots a window has to span for its slot time to be worth reporting. Block times only have second
+//resolution, so over a shorter span their rounding would swamp the drift
+pub const MIN_DRIFT_SLOT_SPAN: u64 = 25;
+
+//Function that compares how long the slots of a window actually took, by its block times, with the nominal slot
+//time. The slot span has to be the slots the time span covers, from the boundary block before the oldest counted
+//one to the newest. Positive drift means slots were slower than nominal, e.g. 0.05 for 420 ms slots. None for
+//short windows
+pub fn slot_time_drift(time_span_seconds: i64, slot_span: u64) -> Option<f64> {
+    if slot_span < MIN_DRIFT_SLOT_SPAN || time_span_seconds <= 0 {
+        return None;
+    }
+
+    let average_slot_seconds = (time_span_seconds as f64) / (slot_span as f64);
+    Some(average_slot_seconds / NOMINAL_SLOT_SECONDS - 1.0)
+}
+
+//Shortest, median and longest time between consecutive blocks of a window, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockIntervals {
+    pub min_ms: u64,
+    pub median_ms: u64,
+    pub max_ms: u64,
+}
+
+//Function that summarizes the time between consecutive blocks, given their block times in walk order.
+//Block times only have second resolution, so the intervals are whole seconds. None with fewer than two blocks
+pub fn block_intervals(block_times: &[i64]) -> Option<BlockIntervals> {
+    let mut intervals: Vec<u64> = block_times
+        .windows(2)
+        .map(|pair| pair[0].abs_diff(pair[1]).saturating_mul(1000))
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    intervals.sort_unstable();
+
+    Some(BlockIntervals {
+        min_ms: intervals[0],
+        median_ms: intervals[intervals.len().div_ceil(2) - 1],
+        max_ms: intervals[intervals.len() - 1],
+    })
+}
+
+//Function that counts the number of transactions per second.
+//A window without transactions, or without any time between its oldest and newest block, reports 0.0
+pub fn calculate_tps(oldest_timestamp: i64, newest_timestamp: i64, transaction_count: u64) -> f64 {
+    let total_seconds_diff = newest_timestamp.saturating_sub(oldest_timestamp);
+
+    tps_from_span(transaction_count, total_seconds_diff as f64)
+}
+
+//Fewest counted blocks a window needs for TPS to be worked out with its boundary blocks trimmed off
+pub const MIN_TRIMMED_BLOCKS: usize = 3;
+
+//Function that works out TPS without the newest and oldest counted block, which only partly overlap the window,
+//given the block time and transaction count of every counted block. The oldest block left then plays the
+//window's excluded start, like the block before the window does untrimmed. None for windows too small to trim
+pub fn trimmed_tps(blocks: &[(i64, u64)]) -> Option<f64> {
+    if blocks.len() < MIN_TRIMMED_BLOCKS {
+        return None;
+    }
+
+    let mut blocks = blocks.to_vec();
+    blocks.sort_by_key(|(block_time, _)| std::cmp::Reverse(*block_time));
+
+    let interior = &blocks[1..blocks.len() - 1];
+    let transactions: u64 = interior.iter().map(|(_, count)| count).sum();
+
+    Some(calculate_tps(blocks[blocks.len() - 1].0, blocks[1].0, transactions))
+}
+
+//Function that counts the number of transactions per second over an explicit time span in seconds.
+//A span that is zero, negative or not a number reports 0.0, as does a result that isn't finite
+pub fn tps_from_span(count: u64, seconds: f64) -> f64 {
+    if seconds.is_nan() || seconds <= 0.0 {
+        return 0.0;
+    }
+
+    let transactions_per_second = (count as f64) / seconds;
+
+    if transactions_per_second.is_nan() || transactions_per_second.is_infinite() {
+        return 0.0;
+    }
+
+    transactions_per_second
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::address_lookup_table_account::AddressLookupTableAccount;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{ AccountMeta, Instruction };
+    use solana_sdk::message::{ v0::{ self, LoadedAddresses }, VersionedMessage };
+    use solana_sdk::signature::Signature;
+    use solana_sdk::transaction::TransactionVersion;
+    use solana_transaction_status::{
+        EncodableWithMeta,
+        Reward,
+        TransactionStatusMeta,
+        UiTransactionEncoding,
+    };
+
+    use super::*;
+    use crate::testutil::{ block, failed_tx, transaction, user_tx, vote_tx, DEFAULT_FEE };
+
+    #[test]
+    fn empty_block_counts_no_user_transactions() {
+        assert_eq!(analyze_block(&block(7, 1_000, Vec::new()), &AnalysisOptions::default()).user_transactions, 0);
+        assert_eq!(sum_fees(&block(7, 1_000, Vec::new())), 0);
+    }
+
+    #[test]
+    fn window_without_transactions_has_zero_tps() {
+        assert_eq!(calculate_tps(1_000, 1_060, 0), 0.0);
+        assert_eq!(calculate_tps(1_000, 1_000, 0), 0.0);
+    }
+
+    #[test]
+    fn fees_are_split_between_successful_and_failed_transactions() {
+        let program_id = Pubkey::new_unique();
+        let priority_tx = transaction(&[program_id], TransactionStatusMeta {
+            fee: 3 * DEFAULT_FEE,
+            ..TransactionStatusMeta::default()
+        });
+        let block = block(
+            7,
+            1_000,
+            vec![user_tx(program_id), priority_tx, vote_tx(), failed_tx(program_id), failed_tx(program_id)]
+        );
+
+        assert_eq!(split_fees_by_status(&block), (5 * DEFAULT_FEE, 2 * DEFAULT_FEE));
+        assert_eq!(sum_fees(&block), 7 * DEFAULT_FEE);
+
+        let analysis = analyze_block(&block, &AnalysisOptions::default());
+        assert_eq!(analysis.fees_from_successful, 5 * DEFAULT_FEE);
+        assert_eq!(analysis.fees_from_failed, 2 * DEFAULT_FEE);
+    }
+
+    #[test]
+    fn zero_instruction_transaction_is_empty_not_vote() {
+        let empty_tx = transaction(&[], TransactionStatusMeta::default());
+        let block = block(7, 1_000, vec![empty_tx, vote_tx(), user_tx(Pubkey::new_unique())]);
+        let transactions = decode_transactions(&block);
+        let infrastructure_programs = AnalysisOptions::default().infrastructure_programs;
+
+        assert!(is_empty_transaction(&transactions[0].1));
+        assert!(!is_infrastructure_transaction(&transactions[0].1, &infrastructure_programs));
+        assert!(is_infrastructure_transaction(&transactions[1].1, &infrastructure_programs));
+
+        let analysis = analyze_block(&block, &AnalysisOptions::default());
+        assert_eq!(analysis.empty_transactions, 1);
+        assert_eq!(analysis.user_transactions, 1);
+        assert_eq!(analysis.total_transactions, 3);
+    }
+
+    #[test]
+    fn parse_pubkey_reads_base58_pubkeys() {
+        let pubkey = Pubkey::new_unique();
+
+        assert_eq!(parse_pubkey(&pubkey.to_string()).unwrap(), pubkey);
+        assert_eq!(parse_pubkey("11111111111111111111111111111111").unwrap(), solana_sdk::system_program::id());
+    }
+
+    #[test]
+    fn parse_pubkey_rejects_too_short_input() {
+        match parse_pubkey("1111") {
+            Err(TpsError::InvalidPubkey { input, reason }) => {
+                assert_eq!(input, "1111");
+                assert!(reason.contains("32 bytes"), "{}", reason);
+            }
+            other => panic!("expected an invalid pubkey, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_pubkey_rejects_characters_outside_base58() {
+        //0, O, I and l aren't in the base58 alphabet
+        let input = "0OIl1111111111111111111111111111";
+
+        match parse_pubkey(input) {
+            Err(TpsError::InvalidPubkey { reason, .. }) => assert!(reason.contains("base58"), "{}", reason),
+            other => panic!("expected an invalid pubkey, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn v0_transaction_loading_addresses_uses_lookup_tables() {
+        let loaded = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![AccountMeta::new(loaded, false)]);
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![loaded],
+        };
+        let message = v0::Message::try_compile(&Pubkey::new_unique(), &[instruction], &[table], Hash::new_unique())
+            .unwrap();
+        let transaction = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message: VersionedMessage::V0(message),
+        };
+        let meta = TransactionStatusMeta {
+            fee: DEFAULT_FEE,
+            loaded_addresses: LoadedAddresses {
+                writable: vec![loaded],
+                readonly: Vec::new(),
+            },
+            ..TransactionStatusMeta::default()
+        };
+        let lookup_table_tx = EncodedTransactionWithStatusMeta {
+            transaction: transaction.encode_with_meta(UiTransactionEncoding::Base64, &meta),
+            meta: Some(meta.into()),
+            version: Some(TransactionVersion::Number(0)),
+        };
+        let block = block(7, 1_000, vec![lookup_table_tx, user_tx(Pubkey::new_unique())]);
+
+        assert!(uses_lookup_tables(block.transactions[0].meta.as_ref()));
+        assert!(!uses_lookup_tables(block.transactions[1].meta.as_ref()));
+        assert!(!uses_lookup_tables(None));
+
+        let analysis = analyze_block(&block, &AnalysisOptions::default());
+        assert_eq!(analysis.user_transactions, 2);
+        assert_eq!(analysis.lookup_table_transactions, 1);
+    }
+
+    fn fee_reward(lamports: i64) -> Reward {
+        Reward {
+            pubkey: Pubkey::new_unique().to_string(),
+            lamports,
+            post_balance: 1_000_000,
+            reward_type: Some(RewardType::Fee),
+            commission: None,
+        }
+    }
+
+    #[test]
+    fn empty_block_with_rewards_is_reward_only() {
+        let mut reward_only = block(7, 1_000, Vec::new());
+        reward_only.rewards = vec![fee_reward(2_500)];
+        let mut with_transactions = block(8, 1_001, vec![user_tx(Pubkey::new_unique())]);
+        with_transactions.rewards = vec![fee_reward(2_500)];
+
+        assert!(is_reward_only_block(&reward_only));
+        assert!(!is_reward_only_block(&with_transactions));
+        assert!(!is_reward_only_block(&block(9, 1_002, Vec::new())));
+
+        let analysis = analyze_block(&reward_only, &AnalysisOptions::default());
+        assert_eq!(analysis.reward_only_blocks, 1);
+        assert_eq!(analysis.user_transactions, 0);
+        assert_eq!(analysis.rewards.fee, 2_500);
+    }
+
+    #[test]
+    fn parse_counted_instruction_reads_program_and_discriminator() {
+        let program_id = Pubkey::new_unique();
+        let counted = parse_counted_instruction(&format!("{}:0xa1b2", program_id)).unwrap();
+
+        assert_eq!(counted.program_id, program_id);
+        assert_eq!(counted.discriminator, vec![0xa1, 0xb2]);
+        assert_eq!(counted.label(), format!("{}:a1b2", program_id));
+    }
+
+    #[test]
+    fn parse_counted_instruction_rejects_non_ascii_discriminator() {
+        let input = format!("{}:aé1", Pubkey::new_unique());
+
+        assert!(matches!(parse_counted_instruction(&input), Err(TpsError::InvalidInstruction { .. })));
+    }
+
+    #[test]
+    fn signer_counts_keep_the_busiest_half_when_pruning_ties() {
+        let busiest = Pubkey::new_unique();
+        let mut signers = SignerCounts::default();
+        signers.add(HashMap::from([(busiest, 5)]));
+        //Every other payer is tied, which used to leave nothing but the busiest one
+        signers.add((0..MAX_TRACKED_SIGNERS).map(|_| (Pubkey::new_unique(), 1)).collect());
+
+        assert!(signers.pruned);
+        assert_eq!(signers.counts.len(), MAX_TRACKED_SIGNERS / 2);
+        assert_eq!(signers.counts[&busiest], 5);
+    }
+
+    #[test]
+    fn signer_counts_sum_payers_across_blocks() {
+        let payer = Pubkey::new_unique();
+        let mut signers = SignerCounts::default();
+        signers.add(HashMap::from([(payer, 2)]));
+        signers.add(HashMap::from([(payer, 3)]));
+
+        assert_eq!(signers.counts[&payer], 5);
+        assert!(!signers.pruned);
+    }
+
+    #[test]
+    fn parse_counted_instruction_rejects_odd_and_missing_discriminator() {
+        let program_id = Pubkey::new_unique();
+
+        assert!(parse_counted_instruction(&format!("{}:abc", program_id)).is_err());
+        assert!(parse_counted_instruction(&format!("{}:", program_id)).is_err());
+        assert!(parse_counted_instruction(&program_id.to_string()).is_err());
+    }
+
+    #[test]
+    fn undecodable_transaction_is_left_out_of_the_block() {
+        let program_id = Pubkey::new_unique();
+        let mut undecodable = user_tx(program_id);
+        undecodable.transaction = EncodedTransaction::Binary("not base64!".to_string(), TransactionBinaryEncoding::Base64);
+        let block = block(7, 1_000, vec![undecodable, user_tx(program_id)]);
+
+        let analysis = analyze_block(&block, &AnalysisOptions::default());
+
+        assert_eq!(decode_transactions(&block).len(), 1);
+        assert_eq!(analysis.total_transactions, 1);
+        assert_eq!(analysis.user_transactions, 1);
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs::{ self, File };
+use std::io::{ BufReader, BufWriter, Read, Write };
+use std::path::{ Path, PathBuf };
+
+use clap::ValueEnum;
+use flate2::{ read::GzDecoder, write::GzEncoder, Compression };
+use solana_transaction_status::EncodedConfirmedBlock;
+
+use crate::error::TpsError;
+use crate::records::{ BlockRecord, BLOCK_RECORD_VERSION };
+use crate::source::{ clone_block, BlockSource };
+
+//How cached block files are stored on disk
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheCompression {
+    //Plain JSON, one file per block
+    None,
+    //Gzip-compressed JSON, one file per block. Blocks compress well, which matters on mainnet
+    #[default]
+    Gzip,
+}
+
+impl CacheCompression {
+    fn extension(self) -> &'static str {
+        match self {
+            CacheCompression::None => "json",
+            CacheCompression::Gzip => "json.gz",
+        }
+    }
+}
+
+//Block source that keeps every block it fetches in a directory, so scans over the same slots
+//don't fetch them again. Cached files are read whatever their compression, so the setting
+//can change between runs
+pub struct CachedBlockSource<S> {
+    inner: S,
+    dir: PathBuf,
+    compression: CacheCompression,
+}
+
+impl<S: BlockSource> CachedBlockSource<S> {
+    pub fn new(inner: S, dir: &Path, compression: CacheCompression) -> Result<Self, TpsError> {
+        fs::create_dir_all(dir)?;
+
+        Ok(CachedBlockSource {
+            inner,
+            dir: dir.to_path_buf(),
+            compression,
+        })
+    }
+
+    fn block_path(&self, slot: u64, compression: CacheCompression) -> PathBuf {
+        self.dir.join(format!("{}.{}", slot, compression.extension()))
+    }
+
+    //Function that reads a cached block, if there is a usable one. A damaged file counts as a miss
+    fn read_cached(&self, slot: u64) -> Option<EncodedConfirmedBlock> {
+        for compression in [CacheCompression::Gzip, CacheCompression::None] {
+            let path = self.block_path(slot, compression);
+            if !path.exists() {
+                continue;
+            }
+
+            match read_block_file(&path, compression) {
+                Ok(record) if record.version == BLOCK_RECORD_VERSION && record.slot == slot => {
+                    log::debug!("Cache hit for slot {}", slot);
+                    return Some(record.block);
+                }
+                Ok(_) => log::warn!("Ignoring cached block {} written in another format", path.display()),
+                Err(err) => log::warn!("Ignoring unreadable cached block {}: {}", path.display(), err),
+            }
+        }
+
+        None
+    }
+
+    //Function that writes a block to the cache. The file only appears once it is complete
+    fn write_cached(&self, slot: u64, block: &EncodedConfirmedBlock) -> Result<(), TpsError> {
+        let path = self.block_path(slot, self.compression);
+        let partial_path = path.with_extension("partial");
+
+        let record = BlockRecord {
+            version: BLOCK_RECORD_VERSION,
+            slot,
+            block: clone_block(block),
+        };
+        let json = serde_json::to_vec(&record).expect("block record serializes to JSON");
+
+        let mut writer = BufWriter::new(File::create(&partial_path)?);
+        match self.compression {
+            CacheCompression::None => writer.write_all(&json)?,
+            CacheCompression::Gzip => {
+                let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+                encoder.write_all(&json)?;
+                encoder.finish()?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+
+        fs::rename(&partial_path, &path)?;
+        Ok(())
+    }
+}
+
+//Function that reads one cached block file, decompressing it if needed
+fn read_block_file(path: &Path, compression: CacheCompression) -> Result<BlockRecord, TpsError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut json = Vec::new();
+
+    match compression {
+        CacheCompression::None => reader.read_to_end(&mut json)?,
+        CacheCompression::Gzip => GzDecoder::new(reader).read_to_end(&mut json)?,
+    };
+
+    serde_json::from_slice(&json).map_err(|err| TpsError::InvalidRecord {
+        line: 0,
+        reason: err.to_string(),
+    })
+}
+
+impl<S: BlockSource> BlockSource for CachedBlockSource<S> {
+    fn get_slot(&self) -> Result<u64, TpsError> {
+        self.inner.get_slot()
+    }
+
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+        if let Some(block) = self.read_cached(slot) {
+            return Ok(block);
+        }
+
+        let block = self.inner.get_block(slot)?;
+
+        //The block was fetched, so a cache that can't be written only costs a refetch next time
+        if let Err(err) = self.write_cached(slot, &block) {
+            log::warn!("Could not cache block {}: {}", slot, err);
+        }
+
+        Ok(block)
+    }
+
+    //Cached blocks carry their time, anything else is asked for without fetching and caching the whole block
+    fn get_block_time(&self, slot: u64) -> Result<Option<i64>, TpsError> {
+        match self.read_cached(slot) {
+            Some(block) => Ok(block.block_time),
+            None => self.inner.get_block_time(slot),
+        }
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        self.inner.get_blocks(start_slot, end_slot)
+    }
+
+    fn get_first_available_block(&self) -> Result<u64, TpsError> {
+        self.inner.get_first_available_block()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::testutil::{ block, source, user_tx };
+
+    //Directory of its own for every test, emptied up front in case an earlier run left it behind
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("solana_rs-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn gzip_cache_round_trips_blocks() {
+        let dir = cache_dir("gzip");
+        let fetched = block(7, 1_000, vec![user_tx(Pubkey::new_unique())]);
+        let cache = CachedBlockSource::new(source(vec![clone_block(&fetched)]), &dir, CacheCompression::Gzip).unwrap();
+
+        assert_eq!(cache.get_block(7).unwrap(), fetched);
+        let cached = fs::read(dir.join("7.json.gz")).unwrap();
+        assert_eq!(&cached[..2], &[0x1f, 0x8b]);
+
+        //A source without the block can only serve it from the cache
+        let cache = CachedBlockSource::new(source(vec![block(8, 1_001, Vec::new())]), &dir, CacheCompression::Gzip)
+            .unwrap();
+        assert_eq!(cache.get_block(7).unwrap(), fetched);
+        assert_eq!(cache.get_block_time(7).unwrap(), Some(1_000));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_reads_files_written_with_other_compression() {
+        let dir = cache_dir("mixed");
+        let fetched = block(7, 1_000, Vec::new());
+        let cache = CachedBlockSource::new(source(vec![clone_block(&fetched)]), &dir, CacheCompression::None).unwrap();
+        cache.get_block(7).unwrap();
+        assert!(dir.join("7.json").exists());
+
+        let cache = CachedBlockSource::new(source(vec![block(8, 1_001, Vec::new())]), &dir, CacheCompression::Gzip)
+            .unwrap();
+        assert_eq!(cache.get_block(7).unwrap(), fetched);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn damaged_cached_block_is_refetched() {
+        let dir = cache_dir("damaged");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("7.json.gz"), b"not gzip").unwrap();
+        let fetched = block(7, 1_000, Vec::new());
+        let cache = CachedBlockSource::new(source(vec![clone_block(&fetched)]), &dir, CacheCompression::Gzip).unwrap();
+
+        assert_eq!(cache.get_block(7).unwrap(), fetched);
+        assert_eq!(read_block_file(&dir.join("7.json.gz"), CacheCompression::Gzip).unwrap().block, fetched);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,16 @@
+use chrono::{ DateTime, Utc };
+
+//Source of the current time for a scan, so the time it measures can be controlled
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+//The system's wall clock, which scans use unless given another
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::{ self, BufWriter, Write };
+use std::path::Path;
+
+use solana_transaction_status::EncodedConfirmedBlock;
+
+use crate::records::write_block_record;
+
+//CSV file mapping every scanned slot to its block time. Rows are flushed as they are written,
+//so an interrupted scan still leaves a usable file behind
+pub struct SlotDump {
+    writer: BufWriter<File>,
+}
+
+impl SlotDump {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "slot,block_time")?;
+        writer.flush()?;
+
+        Ok(SlotDump { writer })
+    }
+
+    //Row for a slot that produced a block. A block without a time gets an empty timestamp
+    pub fn write_block(&mut self, slot: u64, block_time: Option<i64>) -> io::Result<()> {
+        match block_time {
+            Some(block_time) => writeln!(self.writer, "{},{}", slot, block_time)?,
+            None => writeln!(self.writer, "{},", slot)?,
+        }
+        self.writer.flush()
+    }
+
+    //Rows for the slots strictly between a block and its parent, which were skipped and have no timestamp.
+    //They are written newest first, like the scan itself
+    pub fn write_skipped_between(&mut self, parent_slot: u64, slot: u64) -> io::Result<()> {
+        for skipped_slot in (parent_slot.saturating_add(1)..slot).rev() {
+            writeln!(self.writer, "{},", skipped_slot)?;
+        }
+        self.writer.flush()
+    }
+}
+
+//NDJSON file with every block a scan fetched, exactly as the endpoint returned it, in the record format
+//that --from-file and replay read back. Records are flushed as they are written, like the slot dump
+pub struct BlockDump {
+    writer: BufWriter<File>,
+}
+
+impl BlockDump {
+    //An existing file is replaced, with a warning, since it may hold a dump worth keeping
+    pub fn create(path: &Path) -> io::Result<Self> {
+        if path.exists() {
+            log::warn!("Overwriting existing block dump {}", path.display());
+        }
+
+        Ok(BlockDump {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write_block(&mut self, slot: u64, block: &EncodedConfirmedBlock) -> io::Result<()> {
+        write_block_record(&mut self.writer, slot, block)?;
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,263 @@
+use std::cell::Cell;
+
+use solana_client::client_error::{ ClientError, ClientErrorKind };
+use solana_client::rpc_custom_error;
+use solana_client::rpc_request::RpcError;
+use thiserror::Error;
+
+//Everything that can make a scan fail, so callers can tell the kinds of failure apart
+#[derive(Debug, Error)]
+pub enum TpsError {
+    //The RPC endpoint returned an error. Boxed because ClientError is large
+    #[error("RPC error: {0}")]
+    Rpc(Box<ClientError>),
+
+    //The slot was skipped, or its block isn't available on the endpoint
+    #[error("no block available for slot {0}")]
+    NoBlockAvailable(u64),
+
+    //The block in the slot was cleaned up from the endpoint's ledger. Asking again won't bring it back
+    #[error("block in slot {0} was cleaned up from the ledger")]
+    BlockCleanedUp(u64),
+
+    //The endpoint returned the block without the transaction detail it was asked for, so its transactions
+    //can't be counted. Counting it as empty would under-report
+    #[error("block in slot {0} came back without its transactions")]
+    DetailStripped(u64),
+
+    //The block exists but carries no timestamp, so no time window can be measured from it
+    #[error("block in slot {0} has no block time")]
+    MissingBlockTime(u64),
+
+    //The requested window can't be scanned, e.g. it is negative or covers no blocks
+    #[error("invalid window: {0}")]
+    InvalidWindow(String),
+
+    //The endpoint's history ends inside the requested window. Only returned when asked to fail instead of truncating
+    #[error("history ends before the window: slot {slot} is unavailable, only {covered_seconds} seconds were covered")]
+    InsufficientHistory {
+        slot: u64,
+        covered_seconds: i64,
+    },
+
+    //A line of a per-block record file doesn't match the record format. Line 0 means the file as a whole
+    #[error("invalid block record on line {line}: {reason}")]
+    InvalidRecord {
+        line: usize,
+        reason: String,
+    },
+
+    //A command line value or file entry that should be a pubkey isn't one
+    #[error("'{input}' is not a valid base58 pubkey: {reason}")]
+    InvalidPubkey {
+        input: String,
+        reason: String,
+    },
+
+    //An output template is malformed or names a field the report doesn't have
+    #[error("invalid template: {0}")]
+    InvalidTemplate(String),
+
+    //A command line value that should name an instruction as program:hex-discriminator doesn't
+    #[error("'{input}' is not a valid program:discriminator pair: {reason}")]
+    InvalidInstruction {
+        input: String,
+        reason: String,
+    },
+
+    //A line of a program allowlist file isn't a valid program id
+    #[error("invalid program allowlist on line {line}: {reason}")]
+    InvalidAllowlist {
+        line: usize,
+        reason: String,
+    },
+
+    //Reading or writing one of the input or output files failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    //Reading or writing the run history database failed
+    #[error("run history error: {0}")]
+    History(#[from] rusqlite::Error),
+
+    //Some files of a batch couldn't be summarized. The others were
+    #[error("{failed} of {total} batch inputs failed")]
+    BatchFailed {
+        failed: usize,
+        total: usize,
+    },
+
+    //The RPC node said it is unhealthy or behind the cluster before the scan, so its blocks can't be trusted
+    #[error("endpoint {endpoint} is unhealthy: {reason}")]
+    NodeUnhealthy {
+        endpoint: String,
+        reason: String,
+    },
+
+    //The block subscription couldn't be set up, or stopped delivering blocks
+    #[cfg(feature = "geyser")]
+    #[error("block stream error: {0}")]
+    Stream(String),
+
+    //The scan finished, but more slots were skipped than allowed. The report was still produced
+    #[error("skip rate {skip_rate:.4} is above the maximum of {max_skip_ratio:.4}")]
+    SkipRateExceeded {
+        skip_rate: f64,
+        max_skip_ratio: f64,
+    },
+
+    //The scan finished, but TPS was below the minimum. The report was still produced
+    #[error("TPS {transactions_per_second:.2} is below the minimum of {min_tps:.2}")]
+    TpsBelowMinimum {
+        transactions_per_second: f64,
+        min_tps: f64,
+    },
+
+    //The scan finished, but TPS was above the maximum. The report was still produced
+    #[error("TPS {transactions_per_second:.2} is above the maximum of {max_tps:.2}")]
+    TpsAboveMaximum {
+        transactions_per_second: f64,
+        max_tps: f64,
+    },
+}
+
+//Exit code of a run that failed with a scan or I/O error
+pub const EXIT_FAILURE: i32 = 1;
+//Exit code of a run that failed a health check, on the node or on what the scan found, so monitoring can
+//tell it apart
+pub const EXIT_UNHEALTHY: i32 = 3;
+//Exit code of a run that hit a bug and panicked, when panics are reported as errors
+pub const EXIT_PANIC: i32 = 4;
+
+thread_local! {
+    //Slot whose block the thread is fetching or analyzing, so a panic can tell which block it happened on
+    static CURRENT_SLOT: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+//Function that records the slot the current thread is working on, None once it isn't working on any
+pub fn set_current_slot(slot: Option<u64>) {
+    CURRENT_SLOT.with(|current_slot| current_slot.set(slot));
+}
+
+//Function that returns the slot the current thread last said it is working on
+pub fn current_slot() -> Option<u64> {
+    CURRENT_SLOT.with(Cell::get)
+}
+
+impl TpsError {
+    //Function that returns the process exit code for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            | TpsError::NodeUnhealthy { .. }
+            | TpsError::SkipRateExceeded { .. }
+            | TpsError::TpsBelowMinimum { .. }
+            | TpsError::TpsAboveMaximum { .. } => EXIT_UNHEALTHY,
+            _ => EXIT_FAILURE,
+        }
+    }
+}
+
+impl From<ClientError> for TpsError {
+    fn from(err: ClientError) -> Self {
+        TpsError::Rpc(Box::new(err))
+    }
+}
+
+//JSON-RPC error code for an error inside the node while handling the request
+const JSON_RPC_INTERNAL_ERROR: i64 = -32603;
+
+//Function that tells whether a request failed for a reason that may pass if it is sent again: a timeout,
+//a connection that couldn't be made or was reset, rate limiting, an HTTP 5xx, or a node that is behind.
+//Anything else, like invalid parameters or a slot the node will never have, fails the same way every time
+pub fn is_transient(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(err) => {
+            let transient_status = err.status().is_some_and(|status| {
+                status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            });
+            transient_status || err.is_timeout() || err.is_connect() || err.is_request() || err.is_body()
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            matches!(
+                *code,
+                rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY | JSON_RPC_INTERNAL_ERROR | 429
+            )
+        }
+        //The client asks for the node version before some requests and reports that failing only by message
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(message)) => {
+            message.starts_with("cluster version query failed")
+        }
+        _ => false,
+    }
+}
+
+//Function that tells whether a request failed because the slot it asked about has no block on the endpoint:
+//the slot was skipped, its block was cleaned up from the ledger or isn't in long-term storage. Sending the
+//request again gets the same answer, so these errors are never transient
+pub fn is_slot_unavailable(err: &ClientError) -> bool {
+    matches!(
+        rpc_error_code(err),
+        Some(
+            rpc_custom_error::JSON_RPC_SERVER_ERROR_SLOT_SKIPPED
+                | rpc_custom_error::JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED
+                | rpc_custom_error::JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP
+                | rpc_custom_error::JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+        )
+    )
+}
+
+//Function that returns the JSON-RPC error code of an error response, if the error is one
+pub fn rpc_error_code(err: &ClientError) -> Option<i64> {
+    match err.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => Some(*code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::rpc_response_error;
+
+    #[test]
+    fn overloaded_or_lagging_nodes_are_transient() {
+        //Node is behind, internal error and too many requests
+        for code in [-32005, -32603, 429] {
+            assert!(is_transient(&rpc_response_error(code, "try again")), "code {}", code);
+        }
+    }
+
+    #[test]
+    fn io_errors_and_failed_version_queries_are_transient() {
+        let io_error = ClientError::from(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        let version_query = ClientError::from(
+            RpcError::RpcRequestError("cluster version query failed: connection closed".to_string())
+        );
+
+        assert!(is_transient(&io_error));
+        assert!(is_transient(&version_query));
+    }
+
+    #[test]
+    fn invalid_requests_are_permanent() {
+        let invalid_params = rpc_response_error(-32602, "Invalid params");
+        let other_request_error = ClientError::from(RpcError::RpcRequestError("bad request".to_string()));
+
+        assert!(!is_transient(&invalid_params));
+        assert!(!is_transient(&other_request_error));
+        assert!(!is_slot_unavailable(&invalid_params));
+    }
+
+    #[test]
+    fn slots_without_a_block_are_unavailable_and_permanent() {
+        //Slot skipped, skipped in long-term storage, block cleaned up and block not available
+        for code in [-32007, -32009, -32001, -32004] {
+            let err = rpc_response_error(code, "no block");
+
+            assert!(is_slot_unavailable(&err), "code {}", code);
+            assert!(!is_transient(&err), "code {}", code);
+            assert_eq!(rpc_error_code(&err), Some(code));
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use solana_transaction_status::EncodedConfirmedBlock;
+
+use crate::analysis::{ analyze_block, AnalysisOptions };
+use crate::error::TpsError;
+use crate::source::BlockSource;
+
+//One line of the follow mode, summarizing a single block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSummary {
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub user_transactions: u64,
+    pub vote_transactions: u64,
+    pub total_transactions: u64,
+}
+
+impl BlockSummary {
+    //Function that summarizes a block the way the scan counts it
+    pub fn new(slot: u64, block: &EncodedConfirmedBlock, options: &AnalysisOptions) -> Self {
+        let analysis = analyze_block(block, options);
+        let vote_transactions = analysis.total_transactions
+            .saturating_sub(analysis.user_transactions)
+            .saturating_sub(analysis.empty_transactions)
+            .saturating_sub(analysis.filtered_out);
+
+        BlockSummary {
+            slot,
+            block_time: block.block_time,
+            user_transactions: analysis.user_transactions,
+            vote_transactions,
+            total_transactions: analysis.total_transactions,
+        }
+    }
+}
+
+impl fmt::Display for BlockSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let block_time = match self.block_time {
+            Some(block_time) => block_time.to_string(),
+            None => "-".to_string(),
+        };
+
+        write!(
+            f,
+            "slot {} time {} user {} vote {} total {}",
+            self.slot,
+            block_time,
+            self.user_transactions,
+            self.vote_transactions,
+            self.total_transactions
+        )
+    }
+}
+
+//Function that tails the chain like tail -f: it polls for the tip every interval and hands every block produced
+//since the previous poll to on_block, oldest first, so a tip that moved several slots is caught up in order.
+//It starts at the given distance behind the tip, where blocks have settled, and only returns on an error
+pub fn follow(
+    client: &impl BlockSource,
+    options: &AnalysisOptions,
+    tip_lag: u64,
+    poll_interval: Duration,
+    mut on_block: impl FnMut(BlockSummary)
+) -> Result<(), TpsError> {
+    let mut last_slot = client.get_slot()?.saturating_sub(tip_lag);
+    log::info!("Following new blocks after slot {}", last_slot);
+
+    loop {
+        thread::sleep(poll_interval);
+
+        let settled_slot = client.get_slot()?.saturating_sub(tip_lag);
+        if settled_slot <= last_slot {
+            continue;
+        }
+
+        for slot in client.get_blocks(last_slot + 1, settled_slot)? {
+            match client.get_block(slot) {
+                Ok(block) => on_block(BlockSummary::new(slot, &block, options)),
+                //Listed a moment ago, but gone now, e.g. pruned. There is nothing left to summarize
+                Err(TpsError::NoBlockAvailable(_)) => log::warn!("Block in slot {} is no longer available", slot),
+                Err(err) => {
+                    return Err(err);
+                }
+            }
+        }
+
+        last_slot = settled_slot;
+    }
+}
@@ -0,0 +1,645 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::sync::{ Arc, Condvar, Mutex, MutexGuard };
+use std::thread;
+use std::time::Duration;
+
+use futures::StreamExt;
+use solana_sdk::{
+    hash::{ Hash, HASH_BYTES },
+    instruction::CompiledInstruction,
+    message::{
+        v0::{ self, LoadedAddresses, MessageAddressTableLookup },
+        Message,
+        MessageHeader,
+        VersionedMessage,
+    },
+    pubkey::Pubkey,
+    signature::{ Signature, SIGNATURE_BYTES },
+    transaction::{ TransactionError, VersionedTransaction },
+};
+use solana_transaction_status::{
+    EncodableWithMeta,
+    EncodedConfirmedBlock,
+    EncodedTransactionWithStatusMeta,
+    InnerInstruction,
+    InnerInstructions,
+    Reward,
+    RewardType,
+    TransactionStatusMeta,
+    UiTransactionEncoding,
+};
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::tonic::service::Interceptor;
+use yellowstone_grpc_proto::prelude::{
+    self as proto,
+    subscribe_update::UpdateOneof,
+    CommitmentLevel,
+    SubscribeRequestFilterBlocks,
+    SubscribeUpdateBlock,
+    SubscribeUpdateTransactionInfo,
+};
+
+use crate::error::TpsError;
+use crate::scan::ScanWindow;
+use crate::source::{ clone_block, BlockSource, Commitment };
+
+//Blocks a streamed source keeps by default, about ten minutes of slots
+pub const DEFAULT_STREAMED_BLOCKS: usize = 1500;
+
+//Slots are about 400ms apart, so a second of the chain holds at most this many blocks
+const BLOCKS_PER_SECOND: usize = 3;
+
+//How long the stream may go without a block before reads give up on it
+pub const STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+//Pause before subscribing again after the stream broke off
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+//Block source fed by a Geyser-style subscription instead of JSON RPC requests. The subscription pushes
+//every block as it streams in and the scan reads them back like any other source, so counting doesn't
+//wait on a request per block. Only the newest blocks are kept, older ones count as cleaned up
+pub struct StreamedBlockSource {
+    blocks: Mutex<BTreeMap<u64, EncodedConfirmedBlock>>,
+    arrived: Condvar,
+    capacity: usize,
+    //Why the subscription last broke off, reported when reads time out waiting for blocks
+    stream_error: Mutex<Option<String>>,
+}
+
+impl StreamedBlockSource {
+    pub fn new(capacity: usize) -> Self {
+        StreamedBlockSource {
+            blocks: Mutex::new(BTreeMap::new()),
+            arrived: Condvar::new(),
+            capacity: capacity.max(1),
+            stream_error: Mutex::new(None),
+        }
+    }
+
+    //Function that connects to a Yellowstone gRPC endpoint and keeps the returned source fed with the blocks
+    //it streams, from a thread of its own. The subscription starts again whenever it breaks off
+    pub fn subscribe(
+        endpoint: &str,
+        x_token: Option<&str>,
+        commitment: Commitment,
+        capacity: usize
+    ) -> Result<Arc<Self>, TpsError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| TpsError::Stream(format!("could not start the stream runtime: {}", err)))?;
+
+        //The channel is set up lazily, but that still has to happen inside the runtime
+        let client = {
+            let _runtime = runtime.enter();
+            GeyserGrpcClient::connect(endpoint.to_string(), x_token.map(str::to_string), None)
+                .map_err(|err| TpsError::Stream(format!("could not connect to {}: {}", endpoint, err)))?
+        };
+
+        let source = Arc::new(StreamedBlockSource::new(capacity));
+        let fed_source = Arc::clone(&source);
+        let endpoint = endpoint.to_string();
+        thread::Builder::new()
+            .name("geyser".to_string())
+            .spawn(move || runtime.block_on(stream_blocks(client, &endpoint, commitment, &fed_source)))?;
+
+        Ok(source)
+    }
+
+    //Function that adds a block that streamed in, dropping the oldest kept block once the source is full
+    pub fn push(&self, slot: u64, block: EncodedConfirmedBlock) {
+        let mut blocks = self.blocks.lock().unwrap();
+        blocks.insert(slot, block);
+        while blocks.len() > self.capacity {
+            blocks.pop_first();
+        }
+        drop(blocks);
+
+        self.arrived.notify_all();
+    }
+
+    //Function that records why the subscription broke off
+    pub fn set_stream_error(&self, err: String) {
+        *self.stream_error.lock().unwrap() = Some(err);
+    }
+
+    //Function that returns the error for a slot the source has no block for. Slots older than every kept
+    //block may have had one, so they are reported as cleaned up and the scan treats them like pruned history
+    fn missing(blocks: &BTreeMap<u64, EncodedConfirmedBlock>, slot: u64) -> TpsError {
+        match blocks.keys().next() {
+            Some(oldest) if slot < *oldest => TpsError::BlockCleanedUp(slot),
+            _ => TpsError::NoBlockAvailable(slot),
+        }
+    }
+
+    //Function that returns the error reads fail with once the stream stayed quiet for too long
+    fn stalled(&self, timeout: Duration) -> TpsError {
+        match self.stream_error.lock().unwrap().as_ref() {
+            Some(err) => TpsError::Stream(format!("no block streamed in for {:?}, last error: {}", timeout, err)),
+            None => TpsError::Stream(format!("no block streamed in for {:?}", timeout)),
+        }
+    }
+
+    //Function that locks the kept blocks once they satisfy the condition, waiting for more to stream in until
+    //then. Fails when the stream goes quiet for longer than the timeout before that
+    fn wait_until(
+        &self,
+        timeout: Duration,
+        condition: impl Fn(&BTreeMap<u64, EncodedConfirmedBlock>) -> bool
+    ) -> Result<MutexGuard<'_, BTreeMap<u64, EncodedConfirmedBlock>>, TpsError> {
+        let mut blocks = self.blocks.lock().unwrap();
+
+        while !condition(&blocks) {
+            let newest_slot = blocks.keys().next_back().copied();
+            let (next_blocks, wait) = self.arrived.wait_timeout(blocks, timeout).unwrap();
+            blocks = next_blocks;
+            if wait.timed_out() && blocks.keys().next_back().copied() == newest_slot {
+                return Err(self.stalled(timeout));
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    //Function that waits until the kept blocks reach back over the whole window, counted from the block the
+    //scan will start at once it stays the given number of slots behind the tip and passes over the warmup blocks
+    pub fn wait_for_window(
+        &self,
+        window: ScanWindow,
+        tip_lag: u64,
+        warmup_blocks: u64,
+        timeout: Duration
+    ) -> Result<(), TpsError> {
+        self.wait_until(timeout, |blocks| window_is_covered(blocks, window, tip_lag, warmup_blocks)).map(|_| ())
+    }
+}
+
+//Function that tells whether the kept blocks cover the window, as seen from the block a scan would start at.
+//A window from a given slot on covers whatever is kept, slots older than the stream are left out of it
+fn window_is_covered(
+    blocks: &BTreeMap<u64, EncodedConfirmedBlock>,
+    window: ScanWindow,
+    tip_lag: u64,
+    warmup_blocks: u64
+) -> bool {
+    let Some(newest_slot) = blocks.keys().next_back() else {
+        return false;
+    };
+    let lagged_slot = newest_slot.saturating_sub(tip_lag);
+    let mut scanned = blocks.range(..=lagged_slot).rev().skip(warmup_blocks as usize);
+
+    match window {
+        ScanWindow::Seconds(window_seconds) => {
+            let Some(start_time) = scanned.next().and_then(|(_, block)| block.block_time) else {
+                return false;
+            };
+            let oldest_time = blocks.values().next().and_then(|block| block.block_time);
+            oldest_time.is_some_and(|oldest_time| oldest_time <= start_time.saturating_sub(window_seconds))
+        }
+        //The block before the window's oldest one bounds its time span
+        ScanWindow::LastBlocks(block_count) => scanned.count() as u64 > block_count,
+        ScanWindow::SinceSlot(_) | ScanWindow::ListedSlots(_) => scanned.next().is_some(),
+    }
+}
+
+//Function that returns how many blocks a streamed source has to keep for the window to fit in it
+pub fn capacity_for_window(window: ScanWindow, tip_lag: u64, warmup_blocks: u64) -> usize {
+    let settling_blocks = tip_lag.saturating_add(warmup_blocks) as usize;
+
+    let window_blocks = match window {
+        ScanWindow::Seconds(window_seconds) => (window_seconds.max(0) as usize).saturating_mul(BLOCKS_PER_SECOND),
+        ScanWindow::LastBlocks(block_count) | ScanWindow::ListedSlots(block_count) => block_count as usize,
+        ScanWindow::SinceSlot(_) => 0,
+    };
+
+    window_blocks.saturating_add(settling_blocks).saturating_add(1).max(DEFAULT_STREAMED_BLOCKS)
+}
+
+impl BlockSource for StreamedBlockSource {
+    fn get_slot(&self) -> Result<u64, TpsError> {
+        let blocks = self.wait_until(STREAM_TIMEOUT, |blocks| !blocks.is_empty())?;
+        Ok(*blocks.keys().next_back().expect("source holds at least one block"))
+    }
+
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+        let blocks = self.blocks.lock().unwrap();
+        blocks.get(&slot).map(clone_block).ok_or_else(|| Self::missing(&blocks, slot))
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        let blocks = self.blocks.lock().unwrap();
+        Ok(blocks.range(start_slot..=end_slot).map(|(slot, _)| *slot).collect())
+    }
+
+    fn get_first_available_block(&self) -> Result<u64, TpsError> {
+        let blocks = self.wait_until(STREAM_TIMEOUT, |blocks| !blocks.is_empty())?;
+        Ok(*blocks.keys().next().expect("source holds at least one block"))
+    }
+}
+
+//Function that subscribes to every block with its transactions and pushes each one into the source, for as
+//long as the process runs. A broken off stream is recorded on the source and subscribed to again
+async fn stream_blocks<F: Interceptor>(
+    mut client: GeyserGrpcClient<F>,
+    endpoint: &str,
+    commitment: Commitment,
+    source: &StreamedBlockSource
+) {
+    let commitment = match commitment {
+        Commitment::Confirmed => CommitmentLevel::Confirmed,
+        Commitment::Finalized => CommitmentLevel::Finalized,
+    };
+
+    loop {
+        let blocks_filter = SubscribeRequestFilterBlocks {
+            account_include: Vec::new(),
+            include_transactions: Some(true),
+            include_accounts: Some(false),
+            include_entries: Some(false),
+        };
+        let subscription = client.subscribe_once(
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::from([("blocks".to_string(), blocks_filter)]),
+            HashMap::new(),
+            Some(commitment),
+            Vec::new()
+        ).await;
+
+        let err = match subscription {
+            Ok(mut updates) => {
+                log::info!("Subscribed to blocks from {}", endpoint);
+                loop {
+                    match updates.next().await {
+                        Some(Ok(update)) => {
+                            if let Some(UpdateOneof::Block(block)) = update.update_oneof {
+                                let slot = block.slot;
+                                match streamed_block(block) {
+                                    Ok(block) => source.push(slot, block),
+                                    Err(err) => log::warn!("Leaving out the block streamed for slot {}: {}", slot, err),
+                                }
+                            }
+                        }
+                        Some(Err(status)) => break status.to_string(),
+                        None => break "the endpoint ended the stream".to_string(),
+                    }
+                }
+            }
+            Err(err) => err.to_string(),
+        };
+
+        log::warn!("Block stream from {} broke off, subscribing again: {}", endpoint, err);
+        source.set_stream_error(err);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+//Function that turns a block from the stream into the shape the RPC source returns, with its transactions in
+//block order and encoded in Base64
+pub fn streamed_block(block: SubscribeUpdateBlock) -> Result<EncodedConfirmedBlock, String> {
+    let mut transactions = block.transactions;
+    transactions.sort_by_key(|transaction| transaction.index);
+
+    let transactions = transactions
+        .into_iter()
+        .map(streamed_transaction)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(EncodedConfirmedBlock {
+        previous_blockhash: block.parent_blockhash,
+        blockhash: block.blockhash,
+        parent_slot: block.parent_slot,
+        transactions,
+        rewards: block.rewards
+            .map(|rewards| rewards.rewards.into_iter().map(streamed_reward).collect())
+            .unwrap_or_default(),
+        block_time: block.block_time.map(|block_time| block_time.timestamp),
+        block_height: block.block_height.map(|block_height| block_height.block_height),
+    })
+}
+
+fn streamed_transaction(info: SubscribeUpdateTransactionInfo) -> Result<EncodedTransactionWithStatusMeta, String> {
+    let transaction = info.transaction.ok_or("transaction without its message")?;
+    let message = transaction.message.ok_or("transaction without its message")?;
+    let meta = info.meta.ok_or("transaction without its status")?;
+
+    let transaction = VersionedTransaction {
+        signatures: transaction.signatures
+            .iter()
+            .map(|signature| streamed_signature(signature))
+            .collect::<Result<_, _>>()?,
+        message: streamed_message(message)?,
+    };
+    let meta = streamed_meta(meta)?;
+
+    Ok(EncodedTransactionWithStatusMeta {
+        transaction: transaction.encode_with_meta(UiTransactionEncoding::Base64, &meta),
+        meta: Some(meta.into()),
+        version: Some(transaction.version()),
+    })
+}
+
+fn streamed_message(message: proto::Message) -> Result<VersionedMessage, String> {
+    let header = message.header.ok_or("message without its header")?;
+    let header = MessageHeader {
+        num_required_signatures: streamed_u8(header.num_required_signatures)?,
+        num_readonly_signed_accounts: streamed_u8(header.num_readonly_signed_accounts)?,
+        num_readonly_unsigned_accounts: streamed_u8(header.num_readonly_unsigned_accounts)?,
+    };
+    let account_keys = streamed_pubkeys(&message.account_keys)?;
+    if message.recent_blockhash.len() != HASH_BYTES {
+        return Err(format!("blockhash of {} bytes", message.recent_blockhash.len()));
+    }
+    let recent_blockhash = Hash::new(&message.recent_blockhash);
+    let instructions = message.instructions
+        .into_iter()
+        .map(|instruction| {
+            Ok(CompiledInstruction {
+                program_id_index: streamed_u8(instruction.program_id_index)?,
+                accounts: instruction.accounts,
+                data: instruction.data,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if !message.versioned {
+        return Ok(VersionedMessage::Legacy(Message {
+            header,
+            account_keys,
+            recent_blockhash,
+            instructions,
+        }));
+    }
+
+    let address_table_lookups = message.address_table_lookups
+        .into_iter()
+        .map(|lookup| {
+            Ok(MessageAddressTableLookup {
+                account_key: streamed_pubkey(&lookup.account_key)?,
+                writable_indexes: lookup.writable_indexes,
+                readonly_indexes: lookup.readonly_indexes,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(VersionedMessage::V0(v0::Message {
+        header,
+        account_keys,
+        recent_blockhash,
+        instructions,
+        address_table_lookups,
+    }))
+}
+
+//Function that turns the streamed status into the RPC one. Token balances and return data aren't carried
+//over, as nothing the scan reports is read from them
+fn streamed_meta(meta: proto::TransactionStatusMeta) -> Result<TransactionStatusMeta, String> {
+    let status = match meta.err {
+        Some(err) => {
+            let err: TransactionError = bincode::deserialize(&err.err)
+                .map_err(|err| format!("undecodable transaction error: {}", err))?;
+            Err(err)
+        }
+        None => Ok(()),
+    };
+
+    let inner_instructions = meta.inner_instructions
+        .into_iter()
+        .map(|inner_instructions| {
+            let instructions = inner_instructions.instructions
+                .into_iter()
+                .map(|instruction| {
+                    Ok(InnerInstruction {
+                        instruction: CompiledInstruction {
+                            program_id_index: streamed_u8(instruction.program_id_index)?,
+                            accounts: instruction.accounts,
+                            data: instruction.data,
+                        },
+                        stack_height: instruction.stack_height,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            Ok(InnerInstructions {
+                index: streamed_u8(inner_instructions.index)?,
+                instructions,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(TransactionStatusMeta {
+        status,
+        fee: meta.fee,
+        pre_balances: meta.pre_balances,
+        post_balances: meta.post_balances,
+        inner_instructions: (!meta.inner_instructions_none).then_some(inner_instructions),
+        log_messages: (!meta.log_messages_none).then_some(meta.log_messages),
+        pre_token_balances: None,
+        post_token_balances: None,
+        rewards: Some(meta.rewards.into_iter().map(streamed_reward).collect()),
+        loaded_addresses: LoadedAddresses {
+            writable: streamed_pubkeys(&meta.loaded_writable_addresses)?,
+            readonly: streamed_pubkeys(&meta.loaded_readonly_addresses)?,
+        },
+        return_data: None,
+        compute_units_consumed: meta.compute_units_consumed,
+    })
+}
+
+fn streamed_reward(reward: proto::Reward) -> Reward {
+    let reward_type = match proto::RewardType::from_i32(reward.reward_type) {
+        Some(proto::RewardType::Fee) => Some(RewardType::Fee),
+        Some(proto::RewardType::Rent) => Some(RewardType::Rent),
+        Some(proto::RewardType::Staking) => Some(RewardType::Staking),
+        Some(proto::RewardType::Voting) => Some(RewardType::Voting),
+        Some(proto::RewardType::Unspecified) | None => None,
+    };
+
+    Reward {
+        pubkey: reward.pubkey,
+        lamports: reward.lamports,
+        post_balance: reward.post_balance,
+        reward_type,
+        commission: reward.commission.parse().ok(),
+    }
+}
+
+fn streamed_signature(signature: &[u8]) -> Result<Signature, String> {
+    if signature.len() != SIGNATURE_BYTES {
+        return Err(format!("signature of {} bytes", signature.len()));
+    }
+    Ok(Signature::new(signature))
+}
+
+fn streamed_pubkey(pubkey: &[u8]) -> Result<Pubkey, String> {
+    Pubkey::try_from(pubkey).map_err(|_| format!("pubkey of {} bytes", pubkey.len()))
+}
+
+fn streamed_pubkeys(pubkeys: &[Vec<u8>]) -> Result<Vec<Pubkey>, String> {
+    pubkeys.iter().map(|pubkey| streamed_pubkey(pubkey)).collect()
+}
+
+fn streamed_u8(value: u32) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("index {} is out of range", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+    use yellowstone_grpc_proto::prelude::{ MessageHeader as StreamedHeader, UnixTimestamp };
+
+    use super::*;
+    use crate::analysis::{ analyze_block, AnalysisOptions };
+    use crate::testutil::{ block, chain };
+
+    fn filled_source(blocks: Vec<EncodedConfirmedBlock>, capacity: usize) -> StreamedBlockSource {
+        let source = StreamedBlockSource::new(capacity);
+        for block in blocks {
+            source.push(block.block_height.unwrap(), block);
+        }
+        source
+    }
+
+    fn kept_blocks(blocks: Vec<EncodedConfirmedBlock>) -> BTreeMap<u64, EncodedConfirmedBlock> {
+        blocks.into_iter().map(|block| (block.block_height.unwrap(), block)).collect()
+    }
+
+    fn streamed_transaction_info(index: u64, program_id: Pubkey) -> SubscribeUpdateTransactionInfo {
+        let fee_payer = Pubkey::new_unique();
+        SubscribeUpdateTransactionInfo {
+            signature: vec![index as u8; SIGNATURE_BYTES],
+            is_vote: false,
+            transaction: Some(proto::Transaction {
+                signatures: vec![vec![index as u8; SIGNATURE_BYTES]],
+                message: Some(proto::Message {
+                    header: Some(StreamedHeader {
+                        num_required_signatures: 1,
+                        num_readonly_signed_accounts: 0,
+                        num_readonly_unsigned_accounts: 1,
+                    }),
+                    account_keys: vec![fee_payer.to_bytes().to_vec(), program_id.to_bytes().to_vec()],
+                    recent_blockhash: Hash::new_unique().to_bytes().to_vec(),
+                    instructions: vec![proto::CompiledInstruction {
+                        program_id_index: 1,
+                        accounts: vec![0],
+                        data: Vec::new(),
+                    }],
+                    versioned: false,
+                    address_table_lookups: Vec::new(),
+                }),
+            }),
+            meta: Some(proto::TransactionStatusMeta {
+                fee: 5000,
+                pre_balances: vec![10_000, 1],
+                post_balances: vec![5_000, 1],
+                ..Default::default()
+            }),
+            index,
+        }
+    }
+
+    fn streamed_update(slot: u64, transactions: Vec<SubscribeUpdateTransactionInfo>) -> SubscribeUpdateBlock {
+        SubscribeUpdateBlock {
+            slot,
+            blockhash: Hash::new_unique().to_string(),
+            parent_slot: slot - 1,
+            parent_blockhash: Hash::new_unique().to_string(),
+            block_time: Some(UnixTimestamp { timestamp: 1_000 }),
+            transactions,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn full_source_drops_its_oldest_blocks() {
+        let source = filled_source(chain(5, 1_000), 3);
+
+        assert_eq!(source.get_blocks(0, 10).unwrap(), vec![2, 3, 4]);
+        assert_eq!(source.get_slot().unwrap(), 4);
+        assert_eq!(source.get_first_available_block().unwrap(), 2);
+        assert!(matches!(source.get_block(1), Err(TpsError::BlockCleanedUp(1))));
+        assert!(matches!(source.get_block(5), Err(TpsError::NoBlockAvailable(5))));
+    }
+
+    #[test]
+    fn quiet_stream_times_out_with_its_last_error() {
+        let source = StreamedBlockSource::new(10);
+        source.set_stream_error("connection refused".to_string());
+
+        let err = source.wait_for_window(ScanWindow::LastBlocks(1), 0, 0, Duration::from_millis(10)).unwrap_err();
+
+        assert!(matches!(err, TpsError::Stream(message) if message.contains("connection refused")));
+    }
+
+    #[test]
+    fn time_window_is_covered_once_the_oldest_block_bounds_it() {
+        //Blocks at 1000..=1009, the scan starts at slot 7 after a tip lag of 2 and one warmup block
+        let blocks = kept_blocks(chain(10, 1_000));
+
+        assert!(window_is_covered(&blocks, ScanWindow::Seconds(6), 2, 1));
+        assert!(window_is_covered(&blocks, ScanWindow::Seconds(7), 1, 1));
+        assert!(!window_is_covered(&blocks, ScanWindow::Seconds(7), 2, 1));
+        assert!(!window_is_covered(&BTreeMap::new(), ScanWindow::Seconds(1), 0, 0));
+    }
+
+    #[test]
+    fn block_window_needs_the_boundary_block_too() {
+        let blocks = kept_blocks(chain(10, 1_000));
+
+        assert!(window_is_covered(&blocks, ScanWindow::LastBlocks(7), 2, 0));
+        assert!(!window_is_covered(&blocks, ScanWindow::LastBlocks(8), 2, 0));
+    }
+
+    #[test]
+    fn capacity_fits_the_window_and_the_settling_blocks() {
+        assert_eq!(capacity_for_window(ScanWindow::LastBlocks(10), 4, 0), DEFAULT_STREAMED_BLOCKS);
+        assert_eq!(capacity_for_window(ScanWindow::LastBlocks(5_000), 4, 2), 5_007);
+        assert_eq!(capacity_for_window(ScanWindow::Seconds(3_600), 0, 0), 3_600 * BLOCKS_PER_SECOND + 1);
+    }
+
+    #[test]
+    fn streamed_block_counts_like_a_fetched_one() {
+        let program_id = Pubkey::new_unique();
+        let vote_program_id = solana_sdk::vote::program::id();
+        //The stream doesn't promise block order, so the transactions come in reversed here
+        let transactions = vec![streamed_transaction_info(1, vote_program_id), streamed_transaction_info(0, program_id)];
+        let update = streamed_update(8, transactions);
+
+        let streamed = streamed_block(update).unwrap();
+        let analysis = analyze_block(&streamed, &AnalysisOptions::default());
+
+        assert_eq!(streamed.parent_slot, 7);
+        assert_eq!(streamed.block_time, Some(1_000));
+        assert_eq!(analysis.total_transactions, 2);
+        assert_eq!(analysis.user_transactions, 1);
+        assert_eq!(analysis.fees, 10_000);
+
+        let fetched = block(8, 1_000, streamed.transactions.clone());
+        assert_eq!(analyze_block(&fetched, &AnalysisOptions::default()).user_transactions, 1);
+    }
+
+    #[test]
+    fn streamed_failure_keeps_its_error() {
+        let mut transaction = streamed_transaction_info(0, Pubkey::new_unique());
+        let err = TransactionError::InsufficientFundsForFee;
+        let streamed_err = proto::TransactionError { err: bincode::serialize(&err).unwrap() };
+        transaction.meta.as_mut().unwrap().err = Some(streamed_err);
+
+        let streamed = streamed_block(streamed_update(8, vec![transaction])).unwrap();
+
+        let meta = streamed.transactions[0].meta.as_ref().unwrap();
+        assert_eq!(meta.err, Some(err));
+    }
+
+    #[test]
+    fn malformed_transaction_leaves_the_block_out() {
+        let mut transaction = streamed_transaction_info(0, Pubkey::new_unique());
+        transaction.transaction.as_mut().unwrap().signatures = vec![vec![1; 12]];
+
+        let err = streamed_block(streamed_update(8, vec![transaction])).unwrap_err();
+
+        assert!(err.contains("signature of 12 bytes"), "{}", err);
+    }
+}
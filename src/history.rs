@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use chrono::Utc;
+use rusqlite::{ params, Connection, OptionalExtension };
+
+use crate::error::TpsError;
+use crate::report::TpsReport;
+
+//SQLite database with one row per finished scan, so runs can be compared over time and a run
+//can pick up where the previous one stopped
+pub struct RunHistory {
+    connection: Connection,
+}
+
+impl RunHistory {
+    //Opens the database, creating it and its table on first use
+    pub fn open(path: &Path) -> Result<Self, TpsError> {
+        let connection = Connection::open(path)?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS runs (
+                id INTEGER PRIMARY KEY,
+                recorded_at INTEGER NOT NULL,
+                newest_slot INTEGER NOT NULL,
+                oldest_slot INTEGER NOT NULL,
+                transactions_per_second REAL NOT NULL,
+                user_transactions INTEGER NOT NULL,
+                blocks_scanned INTEGER NOT NULL
+            )",
+            []
+        )?;
+
+        Ok(RunHistory { connection })
+    }
+
+    //Newest slot scanned by the most recently recorded run, None before the first run
+    pub fn last_newest_slot(&self) -> Result<Option<u64>, TpsError> {
+        let newest_slot: Option<i64> = self.connection
+            .query_row("SELECT newest_slot FROM runs ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+            .optional()?;
+
+        Ok(newest_slot.map(|newest_slot| newest_slot as u64))
+    }
+
+    //Adds a finished scan to the history
+    pub fn record(&self, report: &TpsReport) -> Result<(), TpsError> {
+        self.connection.execute(
+            "INSERT INTO runs (
+                recorded_at, newest_slot, oldest_slot, transactions_per_second, user_transactions, blocks_scanned
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                Utc::now().timestamp(),
+                report.newest_slot as i64,
+                report.oldest_slot as i64,
+                report.transactions_per_second,
+                report.user_transactions as i64,
+                report.blocks_scanned as i64
+            ]
+        )?;
+
+        Ok(())
+    }
+}
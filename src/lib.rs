@@ -0,0 +1,22 @@
+pub mod analysis;
+pub mod cache;
+pub mod clock;
+pub mod dump;
+pub mod error;
+pub mod follow;
+#[cfg(feature = "geyser")]
+pub mod geyser;
+pub mod history;
+pub mod metrics;
+pub mod pool;
+pub mod prometheus;
+pub mod records;
+pub mod report;
+pub mod retry;
+pub mod scan;
+pub mod sender;
+pub mod source;
+pub mod stop;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+pub mod timing;
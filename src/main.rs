@@ -1,144 +1,1061 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{ self, IsTerminal };
+use std::path::{ Path, PathBuf };
+use std::panic;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{ error::ErrorKind, ArgGroup, CommandFactory, Parser, Subcommand };
 use dotenv::dotenv;
-use chrono::{ DateTime, Utc, NaiveDateTime };
-use solana_client::{ rpc_client::RpcClient, rpc_config::RpcBlockConfig };
-use solana_transaction_status::{ EncodedConfirmedBlock, UiTransactionEncoding };
-
-//Function thhat retrieves block object based on a given block number
-fn get_block(client: &RpcClient, block_num: u64) -> EncodedConfirmedBlock {
-    log::debug!("Getting block number: {}", block_num);
-
-    let config = RpcBlockConfig {
-        encoding: Some(UiTransactionEncoding::Base64),
-        max_supported_transaction_version: Some(0),
-        ..Default::default()
-    };
+use serde::Serialize;
+use solana_client::rpc_client::{ RpcClient, RpcClientConfig };
+use solana_sdk::{ native_token::sol_to_lamports, pubkey::Pubkey };
+use tokio::sync::Semaphore;
+use tracing_subscriber::layer::SubscriberExt;
+
+use solana_rs::analysis::{
+    self,
+    AnalysisOptions,
+    CountedInstruction,
+    ProgramFilter,
+    TpsAverageMethod,
+};
+use solana_rs::cache::{ CacheCompression, CachedBlockSource };
+use solana_rs::dump::BlockDump;
+use solana_rs::error::{ self, TpsError, EXIT_PANIC };
+use solana_rs::follow;
+#[cfg(feature = "geyser")]
+use solana_rs::geyser::{ self, StreamedBlockSource };
+use solana_rs::history::RunHistory;
+use solana_rs::metrics::MetricKind;
+use solana_rs::pool::{ Balance, RoundRobinBlockSource };
+use solana_rs::prometheus;
+use solana_rs::records::FileBlockSource;
+use solana_rs::report::{ self, OfficialTps, OutputFormat, TpsUnit };
+use solana_rs::retry::RetryingBlockSource;
+use solana_rs::scan::{ self, InsufficientHistory, ScanOptions, ScanWindow };
+use solana_rs::sender::{ self, UserAgentSender };
+use solana_rs::source::{ BlockSource, Commitment, TransactionDetailLevel };
+use solana_rs::timing::{ self, TimingLayer };
+
+//Command line options for the TPS calculation
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about,
+    after_help = "Exit codes: 0 on success, 1 when the scan fails, 2 on invalid arguments, \
+        3 when the scan finished but failed --min-tps, --max-tps or --max-skip-ratio, \
+        4 on an internal error with --panic-as-error.\n\n\
+        SOLANA_RPC_ENDPOINT, SOLANA_TPS_WINDOW_SECONDS and SOLANA_COMMITMENT set --rpc-url, --window-seconds and \
+        --commitment, and can be put in a .env file. A flag given on the command line wins over the environment, \
+        which wins over the default"
+)]
+#[command(group(ArgGroup::new("tps_bounds").multiple(true)))]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Length of the window, counted back from the newest block, that TPS is calculated over. Ignored with
+    /// --windows, so a value set in the environment doesn't get in its way
+    #[arg(long, env = "SOLANA_TPS_WINDOW_SECONDS", default_value_t = 60 * 5)]
+    window_seconds: i64,
+
+    /// Calculate TPS over each of these windows, in seconds, from a single scan of the widest one.
+    /// JSON output becomes an array with one report per window
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_delimiter = ',',
+        conflicts_with_all = ["last_slots", "start_slot", "since_last_run", "dry_run"]
+    )]
+    windows: Vec<i64>,
+
+    /// Scan the N most recent produced blocks instead of a time window
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    last_slots: Option<u64>,
+
+    /// Scan every block from this slot up to the newest block instead of a time window
+    #[arg(long, value_name = "SLOT", conflicts_with = "last_slots")]
+    start_slot: Option<u64>,
+
+    /// Start the backward walk from this historical slot instead of the chain tip
+    #[arg(long, value_name = "SLOT")]
+    seed_slot: Option<u64>,
+
+    /// Start the walk at least this many slots behind the live tip, so the newest block has settled.
+    /// Blocks loaded from a file start at the newest one in the file
+    #[arg(long, value_name = "SLOTS", default_value_t = 4)]
+    tip_lag: u64,
+
+    /// Leave the N newest blocks out of the TPS and its time span, as the newest ones are often still filling up.
+    /// This trades a slightly older window for a more stable number
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    warmup: u64,
+
+    /// Write every scanned slot and its block time to this CSV file while scanning. Skipped slots get an empty time
+    #[arg(long, value_name = "PATH")]
+    dump_slots: Option<PathBuf>,
+
+    /// Write every fetched block to this NDJSON file while scanning, for later use with --from-file or replay
+    #[arg(long, value_name = "PATH")]
+    dump_blocks: Option<PathBuf>,
+
+    /// What to do when the endpoint's history ends before the window does
+    #[arg(long, value_enum, default_value_t = InsufficientHistory::Warn)]
+    on_insufficient_history: InsufficientHistory,
+
+    /// How much transaction detail to fetch blocks with. Less detail is faster but can't tell votes from user
+    /// transactions, so TPS then counts every transaction. Options that inspect instructions need full detail
+    #[arg(long, value_enum, default_value_t = TransactionDetailLevel::Full)]
+    transaction_details: TransactionDetailLevel,
+
+    /// Fetch blocks without their rewards, which makes responses smaller on long scans. The summary then has no
+    /// reward totals. Ignored with --dump-blocks, which keeps whole blocks
+    #[arg(long)]
+    no_rewards: bool,
+
+    /// Also work out these metrics over the counted blocks, reported by name, e.g. --metrics tps,fees,cu.
+    /// They are reported on top of the usual summary, which every scan still works out in full
+    #[arg(long, value_enum, value_delimiter = ',')]
+    metrics: Vec<MetricKind>,
+
+    /// Analyze the fetched blocks in parallel once they are all fetched. Fetching itself stays sequential
+    #[arg(long)]
+    parallel_counting: bool,
+
+    /// Keep memory bounded for wide windows: only a sample of the transaction sizes is kept, so the size
+    /// percentiles are estimates, marked as approximate. Other figures stay exact
+    #[arg(long, conflicts_with = "parallel_counting")]
+    streaming: bool,
+
+    /// Also report the N fee payers that paid for the most user transactions, e.g. bots. Needs full detail
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    top_signers: Option<u64>,
+
+    /// Stop the scan after counting this many blocks, even if the window isn't covered yet
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_blocks: Option<u64>,
+
+    /// Stop the scan after this many seconds and report the part of the window scanned so far
+    #[arg(long, value_name = "SECONDS")]
+    max_runtime_seconds: Option<u64>,
+
+    /// Report an internal error (a panic) as a logged error naming the slot being processed, and exit with 4
+    #[arg(long)]
+    panic_as_error: bool,
+
+    /// In text mode, also print every number the TPS figure is worked out from, to check it by hand
+    #[arg(long)]
+    explain_tps: bool,
+
+    /// Also report TPS over consecutive buckets of this many seconds within the window, starting at its oldest
+    /// block, so only the newest bucket can be shorter. In text mode on a terminal they are drawn as a sparkline
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    bucket_seconds: Option<u64>,
+
+    /// Load the blocks from a per-block NDJSON or JSON file, as written by the dump options, instead of RPC
+    #[arg(long, value_name = "PATH")]
+    from_file: Option<PathBuf>,
+
+    /// Keep every fetched block in this directory and read it from there on later scans
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// How blocks in the cache directory are stored. Existing files are read whatever their compression
+    #[arg(long, value_enum, default_value_t = CacheCompression::Gzip, requires = "cache_dir")]
+    cache_compression: CacheCompression,
+
+    /// Record every finished scan in this SQLite database
+    #[arg(long, value_name = "PATH")]
+    history_db: Option<PathBuf>,
+
+    /// Only scan the blocks produced since the last run recorded in the history database.
+    /// The first run falls back to the usual window
+    #[arg(long, requires = "history_db", conflicts_with_all = ["last_slots", "start_slot", "seed_slot"])]
+    since_last_run: bool,
+
+    /// Exit with code 3 once the summary is printed if the skip rate is above this ratio, between 0 and 1
+    #[arg(long, value_name = "RATIO")]
+    max_skip_ratio: Option<f64>,
+
+    /// Also fetch the RPC node's own TPS samples over about the same window and print them next to ours
+    #[arg(long, conflicts_with_all = ["last_slots", "start_slot", "from_file", "since_last_run", "windows"])]
+    compare_to_official: bool,
+
+    /// Exit with code 3 once the summary is printed if user TPS is below this, for alerting from cron or
+    /// gating a CI job
+    #[arg(long, value_name = "TPS", visible_alias = "assert-tps-at-least", group = "tps_bounds")]
+    min_tps: Option<f64>,
+
+    /// Exit with code 3 once the summary is printed if user TPS is above this, e.g. when a CI job expects a
+    /// quiet cluster
+    #[arg(long, value_name = "TPS", visible_alias = "assert-tps-at-most", group = "tps_bounds")]
+    max_tps: Option<f64>,
+
+    /// Check --min-tps and --max-tps against the TPS of all transactions, votes included, instead of user
+    /// transactions
+    #[arg(long, requires = "tps_bounds")]
+    min_tps_total: bool,
+
+    /// Instead of scanning a window, keep printing a line for every new block as it appears, like tail -f
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "windows",
+            "last_slots",
+            "start_slot",
+            "seed_slot",
+            "from_file",
+            "dry_run",
+            "since_last_run",
+            "compare_to_official",
+        ]
+    )]
+    follow: bool,
+
+    /// Also write the final report as JSON to this file
+    #[arg(long, value_name = "PATH", conflicts_with = "windows")]
+    output: Option<PathBuf>,
+
+    /// While scanning, rewrite the --output file with the report so far every this many seconds, marked as
+    /// partial, so a long scan that dies still leaves usable numbers behind
+    #[arg(long, value_name = "SECONDS", requires = "output", value_parser = clap::value_parser!(u64).range(1..))]
+    flush_interval: Option<u64>,
+
+    /// Also write the final report as CSV to this file: one row per bucket with --bucket-seconds, then a
+    /// summary row over the whole window. The first column is bucket or summary
+    #[arg(long, value_name = "PATH", conflicts_with = "windows")]
+    csv: Option<PathBuf>,
+
+    /// Analyze exactly the slots listed on stdin, one per line, and report TPS over the span of their block times
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "windows",
+            "last_slots",
+            "start_slot",
+            "seed_slot",
+            "since_last_run",
+            "follow",
+            "dry_run",
+            "compare_to_official",
+        ]
+    )]
+    slots_from_stdin: bool,
+
+    /// How often --follow polls for new blocks, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 1000, requires = "follow")]
+    follow_interval_ms: u64,
+
+    /// Scan even if an endpoint reports it is unhealthy or behind the cluster. Otherwise scans against RPC
+    /// check every endpoint with getHealth first and stop, with the unhealthy exit code, if one isn't healthy
+    #[arg(long)]
+    no_preflight: bool,
+
+    /// Print an estimate of how expensive the scan would be, without running it
+    #[arg(long, conflicts_with_all = ["last_slots", "start_slot", "from_file"])]
+    dry_run: bool,
+
+    /// How the summary of the scan is printed
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// How TPS is averaged: simple divides by the span of block times, weighted spreads every block's
+    /// transactions over the slots since its parent at the nominal slot time
+    #[arg(long, value_enum, default_value_t = TpsAverageMethod::Simple)]
+    tps_average_method: TpsAverageMethod,
+
+    /// Unit TPS is displayed in. JSON output keeps raw TPS and names the unit separately
+    #[arg(long, value_enum, default_value_t = TpsUnit::Tps)]
+    tps_unit: TpsUnit,
+
+    /// Print JSON on a single line instead of pretty-printed, for piping into other tools
+    #[arg(long)]
+    compact_json: bool,
+
+    /// Print the summary as one line in this format instead, replacing every {field} with the JSON report field
+    /// of that name. {tps}, {user_tx} and {span} are short for transactions_per_second, user_transactions and
+    /// time_span_seconds. E.g. "{tps} tps over {span}s ({user_tx} txns)"
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "format")]
+    template: Option<String>,
+
+    /// RPC endpoint to scan. Can be repeated to spread the scan over several endpoints with --balance
+    #[arg(long, value_name = "URL", env = "SOLANA_RPC_ENDPOINT", default_value = "https://api.devnet.solana.com")]
+    rpc_url: Vec<String>,
+
+    /// How settled blocks have to be to be scanned, and the tip to be counted back from
+    #[arg(long, value_enum, env = "SOLANA_COMMITMENT", default_value_t = Commitment::Finalized)]
+    commitment: Commitment,
+
+    /// Stream blocks from this Yellowstone gRPC endpoint instead of fetching them over RPC. The scan waits
+    /// until the stream reaches back over the whole window, so it starts a window's length after launch
+    #[cfg(feature = "geyser")]
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "SOLANA_GEYSER_URL",
+        conflicts_with_all = [
+            "windows",
+            "start_slot",
+            "seed_slot",
+            "from_file",
+            "cache_dir",
+            "since_last_run",
+            "compare_to_official",
+            "follow",
+            "slots_from_stdin",
+            "dry_run",
+            "fixture_capture",
+        ]
+    )]
+    geyser_url: Option<String>,
+
+    /// Access token sent to the --geyser-url endpoint, for providers that require one
+    #[cfg(feature = "geyser")]
+    #[arg(long, value_name = "TOKEN", env = "SOLANA_GEYSER_X_TOKEN", hide_env_values = true, requires = "geyser_url")]
+    geyser_x_token: Option<String>,
 
-    let block = client.get_block_with_config(block_num, config);
-    let encoded_block: EncodedConfirmedBlock = block.unwrap().into();
+    /// How block requests are spread over the endpoints given with --rpc-url
+    #[arg(long, value_enum, default_value_t = Balance::First)]
+    balance: Balance,
 
-    encoded_block
+    /// Most requests per second sent to each endpoint in use, e.g. a free tier's rate limit. Without
+    /// --balance round-robin only the first endpoint is used, and it is the one limited
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    endpoint_rps: Option<u32>,
+
+    /// User-Agent header sent with every RPC request, for providers that require a client identifier
+    #[arg(long, default_value_t = sender::default_user_agent())]
+    user_agent: String,
+
+    /// How many times an RPC request that failed with a transient error, like a timeout, a rate limit or a 5xx,
+    /// is retried. Permanent errors are never retried
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    rpc_retries: u32,
+
+    /// Most RPC requests in flight at once, over all endpoints and everything the run asks them for
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_concurrent_rpc: Option<u64>,
+
+    /// Also count instructions invoked by programs (inner instructions) in the per-program counts
+    #[arg(long)]
+    include_inner: bool,
+
+    /// Count user transactions calling this program per instruction discriminator (the first 8 bytes of data)
+    #[arg(long, value_name = "PROGRAM_ID", value_parser = analysis::parse_pubkey)]
+    discriminator_program: Option<Pubkey>,
+
+    /// Count calls of a specific instruction, given as PROGRAM_ID:HEX with the bytes its data starts with, e.g. an
+    /// Anchor discriminator. Can be repeated
+    #[arg(long, value_name = "PROGRAM_ID:HEX", value_parser = analysis::parse_counted_instruction)]
+    count_instruction: Vec<CountedInstruction>,
+
+    /// Count system program transfers of at least this many SOL, listing the largest with their signatures
+    #[arg(long, value_name = "SOL", value_parser = parse_sol)]
+    large_transfer_threshold: Option<u64>,
+
+    /// Also report an experimental weighted TPS that counts distinct fee payers per block instead of transactions
+    #[arg(long)]
+    weighted: bool,
+
+    /// Also report TPS without the newest and oldest counted block, which only partly overlap the window
+    #[arg(long)]
+    trim_boundaries: bool,
+
+    /// Only count user transactions invoking at least one of these programs. Can be repeated
+    #[arg(long, value_name = "PROGRAM_ID", value_parser = analysis::parse_pubkey)]
+    include_only_program: Vec<Pubkey>,
+
+    /// Only count user transactions invoking at least one of the programs listed in this file, one id per line,
+    /// and report the count of each. Blank lines and lines starting with # are skipped
+    #[arg(long, value_name = "PATH")]
+    program_allowlist: Option<PathBuf>,
+
+    /// Don't count user transactions invoking this program, even if they invoke an included one. Can be repeated
+    #[arg(long, value_name = "PROGRAM_ID", value_parser = analysis::parse_pubkey)]
+    exclude_program: Vec<Pubkey>,
+
+    /// Count transactions only calling this program as infrastructure, with the votes, instead of as user
+    /// transactions. Can be repeated, and replaces the default of just the vote program
+    #[arg(
+        long,
+        value_name = "PROGRAM_ID",
+        value_parser = analysis::parse_pubkey,
+        default_value = "Vote111111111111111111111111111111111111111"
+    )]
+    infrastructure_program: Vec<Pubkey>,
+
+    /// Print the JSON Schema of the JSON report and exit
+    #[arg(long, hide = true)]
+    print_schema: bool,
+
+    /// Fetch the block in a slot, save it to a file in the record format --from-file reads and exit. Handy for
+    /// attaching the block behind a bug report
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["SLOT", "FILE"],
+        conflicts_with_all = ["from_file", "follow", "dry_run"]
+    )]
+    fixture_capture: Option<Vec<String>>,
+
+    /// Log how much time was spent fetching, decoding and counting once the scan is done
+    #[arg(long)]
+    trace_timing: bool,
+
+    /// Print a one-line breakdown of the time spent fetching, decoding and analyzing once the scan is done
+    #[arg(long)]
+    profile: bool,
 }
 
-//Function that splits the number of txns into vote txns and user txns
-fn count_user_transactions(block: &EncodedConfirmedBlock) -> u64 {
-    let mut user_transactions_count: u64 = 0;
+//Commands other than the default scan against the RPC endpoint
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Recompute the summary offline from a per-block NDJSON file, without touching RPC
+    Replay {
+        /// File with one block record per line
+        #[arg(long, value_name = "FILE")]
+        input: PathBuf,
+    },
+    /// Summarize many per-block NDJSON or JSON files offline, printing one JSON line per file.
+    /// Files that fail are reported and don't stop the others
+    Batch {
+        /// Per-block file, or directory whose .ndjson, .jsonl and .json files are all read. Can be repeated
+        #[arg(long = "jsonl-input", value_name = "PATH", required = true)]
+        inputs: Vec<PathBuf>,
 
-    for transaction_status in &block.transactions {
-        let transaction = transaction_status.transaction.decode().unwrap();
-        let account_keys = transaction.message.static_account_keys();
+        /// How many files are summarized at the same time
+        #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u64).range(1..))]
+        jobs: u64,
+    },
+    /// Check with getHealth that every RPC endpoint is healthy and caught up with the cluster. Exits with the
+    /// unhealthy code if one isn't
+    Healthcheck,
+    /// List the slots in a range that produced a block, as the RPC endpoint reports them
+    Blocks {
+        /// First slot of the range
+        #[arg(long, value_name = "SLOT", required_unless_present = "recent", conflicts_with = "recent")]
+        start: Option<u64>,
 
-        let mut num_vote_instructions = 0;
-        for instruction in transaction.message.instructions() {
-            let program_id_index = instruction.program_id_index;
-            let program_id = account_keys[usize::from(program_id_index)];
+        /// Last slot of the range, the tip when not given
+        #[arg(long, value_name = "SLOT")]
+        end: Option<u64>,
 
-            if program_id == solana_sdk::vote::program::id() {
-                num_vote_instructions += 1;
-                log::debug!("Vote instruction found");
-            } else {
-                log::debug!("User instruction found");
+        /// List the given number of slots up to the end instead of from a start slot
+        #[arg(long, value_name = "SLOTS", value_parser = clap::value_parser!(u64).range(1..))]
+        recent: Option<u64>,
+
+        /// Also list the slots in the range that didn't produce a block
+        #[arg(long)]
+        show_skipped: bool,
+    },
+}
+
+//Function that parses an amount of SOL given on the command line into lamports
+fn parse_sol(input: &str) -> Result<u64, String> {
+    match input.parse::<f64>() {
+        Ok(sol) if sol.is_finite() && sol >= 0.0 => Ok(sol_to_lamports(sol)),
+        _ => Err(format!("'{}' is not a non-negative amount of SOL", input)),
+    }
+}
+
+//Function that creates the RPC client, identifying it with the given user agent where possible.
+//Every request it sends waits for a permit of the concurrency limit, when there is one
+fn build_client(
+    url: &str,
+    user_agent: &str,
+    commitment: Commitment,
+    concurrency_limit: Option<&Arc<Semaphore>>
+) -> RpcClient {
+    match UserAgentSender::new(url, user_agent) {
+        Some(sender) => {
+            let sender = match concurrency_limit {
+                Some(concurrency_limit) => sender.with_concurrency_limit(Arc::clone(concurrency_limit)),
+                None => sender,
+            };
+            RpcClient::new_sender(sender, RpcClientConfig::with_commitment(commitment.into()))
+        }
+        None => {
+            log::warn!("Falling back to the default RPC client, requests are sent without the custom user agent");
+            if concurrency_limit.is_some() {
+                log::warn!("The default RPC client can't be held to --max-concurrent-rpc");
             }
+            RpcClient::new_with_commitment(url.to_string(), commitment.into())
         }
+    }
+}
 
-        if num_vote_instructions == transaction.message.instructions().len() {
-            log::debug!("It's a vote transaction");
-        } else {
-            log::debug!("It's a user transaction");
-            user_transactions_count += 1;
+//Function that builds the scan settings from the command line options
+fn scan_options(cli: &Cli) -> Result<ScanOptions, TpsError> {
+    let mut include_only = cli.include_only_program.clone();
+    if let Some(program_allowlist) = &cli.program_allowlist {
+        include_only.extend(analysis::read_program_allowlist(program_allowlist)?);
+    }
+
+    if cli.no_rewards && cli.dump_blocks.is_some() {
+        log::warn!("Fetching blocks with their rewards despite --no-rewards, --dump-blocks keeps whole blocks");
+    } else if cli.no_rewards && cli.metrics.contains(&MetricKind::Rewards) {
+        log::warn!("Fetching blocks with their rewards despite --no-rewards, the rewards metric needs them");
+    }
+
+    Ok(ScanOptions {
+        analysis: AnalysisOptions {
+            include_inner: cli.include_inner,
+            discriminator_program: cli.discriminator_program,
+            program_filter: ProgramFilter {
+                include_only,
+                exclude: cli.exclude_program.clone(),
+            },
+            infrastructure_programs: cli.infrastructure_program.clone(),
+            large_transfer_threshold: cli.large_transfer_threshold,
+            counted_instructions: cli.count_instruction.clone(),
+        },
+        weighted: cli.weighted,
+        trim_boundaries: cli.trim_boundaries,
+        seed_slot: cli.seed_slot,
+        warmup_blocks: cli.warmup,
+        //A file holds no live tip, so its newest block is as settled as it gets
+        tip_lag: if cli.command.is_some() || cli.from_file.is_some() { 0 } else { cli.tip_lag },
+        dump_slots: cli.dump_slots.clone(),
+        dump_blocks: cli.dump_blocks.clone(),
+        on_insufficient_history: cli.on_insufficient_history,
+        transaction_details: cli.transaction_details,
+        skip_rewards: cli.no_rewards,
+        metrics: cli.metrics.clone(),
+        tps_average_method: cli.tps_average_method,
+        parallel_counting: cli.parallel_counting,
+        streaming: cli.streaming,
+        top_signers: cli.top_signers.map(|top_signers| top_signers as usize),
+        max_blocks: cli.max_blocks,
+        max_runtime: cli.max_runtime_seconds.map(Duration::from_secs),
+        bucket_seconds: cli.bucket_seconds,
+        flush_output: cli.output.clone(),
+        flush_interval: cli.flush_interval.map(Duration::from_secs),
+    })
+}
+
+//Function that scans the window described by the options over the given source and prints the summary
+fn scan_and_print(client: &impl BlockSource, cli: &Cli, official: Option<OfficialTps>) -> Result<(), TpsError> {
+    let history = cli.history_db.as_deref().map(RunHistory::open).transpose()?;
+
+    let last_newest_slot = match &history {
+        Some(history) if cli.since_last_run => history.last_newest_slot()?,
+        _ => None,
+    };
+
+    let options = scan_options(cli)?;
+
+    let window = match (cli.last_slots, cli.start_slot, last_newest_slot) {
+        (Some(block_count), _, _) => ScanWindow::LastBlocks(block_count),
+        (None, Some(start_slot), _) => ScanWindow::SinceSlot(start_slot),
+        (None, None, Some(last_newest_slot)) => {
+            //The last run already counted its newest block, so only the blocks after it are new
+            if !scan::has_blocks_after(client, last_newest_slot, &options)? {
+                log::info!("No new blocks since the last run at slot {}", last_newest_slot);
+                return Ok(());
+            }
+            ScanWindow::SinceSlot(last_newest_slot + 1)
+        }
+        (None, None, None) => {
+            if cli.since_last_run {
+                log::info!("No previous run recorded, scanning the default window");
+            }
+            ScanWindow::Seconds(cli.window_seconds)
+        }
+    };
+
+    let mut reports = if cli.slots_from_stdin {
+        let slots = scan::read_slot_list(io::stdin().lock())?;
+        log::info!("Analyzing the {} slots listed on stdin", slots.len());
+        vec![scan::calculate_for_slots(client, &slots, &options)?]
+    } else if cli.windows.is_empty() {
+        vec![scan::calculate_for_range(client, window, &options)?]
+    } else {
+        scan::calculate_for_windows(client, &cli.windows, &options)?
+    };
+
+    for report in &mut reports {
+        report.unit = cli.tps_unit;
+        report.official = official.clone();
+
+        if let Some(history) = &history {
+            history.record(report)?;
         }
     }
 
-    let vote_transactions_count = block.transactions
-        .len()
-        .checked_sub(user_transactions_count as usize)
-        .expect("Underflow");
+    if let (Some(output), [report]) = (&cli.output, reports.as_slice()) {
+        report::write_json_report(output, report)?;
+    }
 
-    log::debug!("Solana total txns: {}", block.transactions.len());
-    log::debug!("Solana user txns: {}", user_transactions_count);
-    log::debug!("Solana vote txns: {}", vote_transactions_count);
+    if let (Some(csv), [report]) = (&cli.csv, reports.as_slice()) {
+        report::write_csv_report(csv, report)?;
+    }
 
-    user_transactions_count
-}
+    if let Some(template) = &cli.template {
+        for report in &reports {
+            println!("{}", report::render_template(template, report)?);
+        }
+    }
 
-//Function that counts the number of transactions per second
-fn calculate_tps(oldest_timestamp: i64, newest_timestamp: i64, transaction_count: u64) -> f64 {
-    let total_seconds_diff = newest_timestamp.saturating_sub(oldest_timestamp);
+    match (cli.format, reports.as_slice()) {
+        _ if cli.template.is_some() => {}
+        (OutputFormat::Prometheus, [report]) => print!("{}", prometheus::render(report)),
+        (OutputFormat::Prometheus, reports) => print!("{}", prometheus::render_windows(reports)),
+        (OutputFormat::Json, [report]) if cli.windows.is_empty() => {
+            println!("{}", report::render_json(report, cli.compact_json));
+        }
+        (OutputFormat::Json, reports) => println!("{}", report::render_json_windows(reports, cli.compact_json)),
+        (OutputFormat::Text, reports) => {
+            for report in reports {
+                report::print_text(report);
+                if cli.explain_tps {
+                    report::print_tps_explanation(report);
+                }
+                //The sparkline only reads well in a terminal, redirected logs keep to plain lines
+                if io::stderr().is_terminal() {
+                    report::print_sparkline(report);
+                }
+            }
+        }
+        (OutputFormat::Table, reports) => reports.iter().for_each(report::print_table),
+    }
 
-    let total_seconds_diff_f64 = total_seconds_diff as f64;
-    let transaction_count_f64 = transaction_count as f64;
+    for report in &reports {
+        //Averaged the same way as the reported TPS, so the gate checks the number the run prints
+        let transactions_per_second = if cli.min_tps_total {
+            report.tps_average_method.tps(
+                report.oldest_timestamp,
+                report.newest_timestamp,
+                report.total_transactions,
+                report.counted_slots
+            )
+        } else {
+            report.transactions_per_second
+        };
 
-    let mut transactions_per_second = transaction_count_f64 / total_seconds_diff_f64;
+        if let Some(min_tps) = cli.min_tps {
+            if transactions_per_second < min_tps {
+                return Err(TpsError::TpsBelowMinimum { transactions_per_second, min_tps });
+            }
+        }
+        if let Some(max_tps) = cli.max_tps {
+            if transactions_per_second > max_tps {
+                return Err(TpsError::TpsAboveMaximum { transactions_per_second, max_tps });
+            }
+        }
+    }
 
-    if transactions_per_second.is_nan() || transactions_per_second.is_infinite() {
-        transactions_per_second = 0.0;
+    if let Some(max_skip_ratio) = cli.max_skip_ratio {
+        for report in &reports {
+            if report.skip_rate > max_skip_ratio {
+                return Err(TpsError::SkipRateExceeded {
+                    skip_rate: report.skip_rate,
+                    max_skip_ratio,
+                });
+            }
+        }
     }
 
-    transactions_per_second
+    Ok(())
 }
 
-//Function for looping through blocks, counting the total number of transactions. And then finally doing the transactions per second calculation
-fn calculate_for_range(client: &RpcClient, threshold_seconds: i64) {
-    let calculation_start = Utc::now();
+//Function that scans the RPC source, retrying transient request failures, through the block cache if one is set up
+fn scan_rpc(source: &impl BlockSource, cli: &Cli, official: Option<OfficialTps>) -> Result<(), TpsError> {
+    let source = RetryingBlockSource::new(source, cli.rpc_retries);
 
-    let newest_block_number = client.get_slot().unwrap();
-    let mut current_block = get_block(client, newest_block_number);
+    match &cli.cache_dir {
+        Some(cache_dir) => {
+            let source = CachedBlockSource::new(source, cache_dir, cli.cache_compression)?;
+            scan_and_print(&source, cli, official)
+        }
+        None => scan_and_print(&source, cli, official),
+    }
+}
 
-    let newest_timestamp = current_block.block_time.unwrap();
-    let timestamp_threshold = newest_timestamp.checked_sub(threshold_seconds).unwrap();
+//Function that puts the endpoints in a pool when requests are spread over them or held to a rate limit. A pool
+//of the first endpoint alone holds it to the rate limit like any pool. None when the first endpoint takes every
+//request as it comes
+fn endpoint_pool<'a>(cli: &Cli, clients: &'a [RpcClient]) -> Option<RoundRobinBlockSource<&'a RpcClient>> {
+    if clients.len() > 1 && cli.balance != Balance::RoundRobin {
+        log::warn!("Only the first RPC endpoint is used, spread the scan over all with --balance");
+    }
 
-    let mut total_transactions_count: u64 = 0;
+    let endpoints: Vec<(String, &RpcClient)> = match (cli.balance, cli.endpoint_rps) {
+        (Balance::RoundRobin, _) if clients.len() > 1 => cli.rpc_url.iter().cloned().zip(clients).collect(),
+        (_, Some(_)) => vec![(cli.rpc_url[0].clone(), &clients[0])],
+        (_, None) => {
+            return None;
+        }
+    };
 
-    //Loop through the blocks, starting from the newest block, and going back in time
-    let oldest_timestamp = loop {
-        let prev_block_number = current_block.parent_slot;
-        let prev_block = get_block(client, prev_block_number);
+    let pool = RoundRobinBlockSource::new(endpoints);
+    Some(match cli.endpoint_rps {
+        Some(endpoint_rps) => pool.with_rate_limit(endpoint_rps),
+        None => pool,
+    })
+}
 
-        let transactions_count = count_user_transactions(&current_block);
-        let naive_datetime = NaiveDateTime::from_timestamp(current_block.block_time.unwrap(), 0);
-        let utc_dt: DateTime<Utc> = DateTime::from_utc(naive_datetime, Utc);
+//Function that lists the files a batch reads, in filename order. Directories contribute their per-block files
+fn batch_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, TpsError> {
+    let mut paths = Vec::new();
 
-        log::debug!("BLock time: {}", utc_dt.format("%Y-%m-%d %H:%M:%S"));
+    for input in inputs {
+        if !input.is_dir() {
+            paths.push(input.clone());
+            continue;
+        }
+
+        for entry in fs::read_dir(input)? {
+            let path = entry?.path();
+            let is_block_file = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| matches!(extension, "ndjson" | "jsonl" | "json"));
+            if is_block_file {
+                paths.push(path);
+            }
+        }
+    }
 
-        total_transactions_count = total_transactions_count
-            .checked_add(transactions_count)
-            .expect("Overflow");
+    paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)));
+    Ok(paths)
+}
 
-        let prev_block_timestamp = prev_block.block_time.unwrap();
+//Function that summarizes every file of a batch and prints one line per file
+fn run_batch(cli: &Cli, inputs: &[PathBuf], jobs: u64) -> Result<(), TpsError> {
+    let paths = batch_inputs(inputs)?;
+    let options = scan_options(cli)?;
 
-        if prev_block_timestamp <= timestamp_threshold {
-            break prev_block_timestamp;
+    let window = match (cli.last_slots, cli.start_slot) {
+        (Some(block_count), _) => ScanWindow::LastBlocks(block_count),
+        (None, Some(start_slot)) => ScanWindow::SinceSlot(start_slot),
+        (None, None) => ScanWindow::Seconds(cli.window_seconds),
+    };
+
+    log::info!("Summarizing {} files, {} at a time", paths.len(), jobs);
+    let results = scan::calculate_batch(&paths, window, &options, jobs as usize);
+
+    let mut failed = 0;
+    for (path, result) in paths.iter().zip(&results) {
+        if let Err(err) = result {
+            log::error!("Could not summarize {}: {}", path.display(), err);
+            failed += 1;
         }
+        println!("{}", report::render_batch_line(path, result));
+    }
 
-        if prev_block.block_height.unwrap() == 0 {
-            break prev_block_timestamp;
+    if failed > 0 {
+        return Err(TpsError::BatchFailed { failed, total: paths.len() });
+    }
+
+    Ok(())
+}
+
+//Function that checks every RPC endpoint's health, printing one line per endpoint. The first endpoint that
+//isn't healthy, or couldn't be asked, is what the run fails with
+fn run_healthcheck(cli: &Cli) -> Result<(), TpsError> {
+    let mut failure = None;
+
+    for url in &cli.rpc_url {
+        let client = build_client(url, &cli.user_agent, cli.commitment, None);
+        match scan::check_node_health(&client, url) {
+            Ok(()) => println!("{}: healthy", url),
+            Err(err) => {
+                match &err {
+                    TpsError::NodeUnhealthy { reason, .. } => println!("{}: unhealthy, {}", url, reason),
+                    _ => println!("{}: {}", url, err),
+                }
+                failure.get_or_insert(err);
+            }
         }
+    }
 
-        current_block = prev_block;
+    match failure {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+//Function that prints the slots of a range that produced a block, and the skipped ones if asked to
+fn run_blocks(
+    client: &impl BlockSource,
+    cli: &Cli,
+    start: Option<u64>,
+    end: Option<u64>,
+    recent: Option<u64>,
+    show_skipped: bool
+) -> Result<(), TpsError> {
+    let end_slot = match end {
+        Some(end_slot) => end_slot,
+        None => client.get_slot()?,
+    };
+    let start_slot = match (start, recent) {
+        (Some(start_slot), _) => start_slot,
+        (None, Some(recent)) => end_slot.saturating_sub(recent - 1),
+        (None, None) => end_slot,
     };
+    if start_slot > end_slot {
+        return Err(TpsError::InvalidWindow(format!("start slot {} is after the end slot {}", start_slot, end_slot)));
+    }
 
-    let transactions_per_second = calculate_tps(
-        oldest_timestamp,
-        newest_timestamp,
-        total_transactions_count
+    let produced_slots = scan::produced_slots(client, start_slot, end_slot)?;
+    log::info!(
+        "{} of the {} slots from {} to {} produced a block",
+        produced_slots.len(),
+        end_slot - start_slot + 1,
+        start_slot,
+        end_slot
     );
 
-    let calculation_end = Utc::now();
+    let produced: HashSet<u64> = produced_slots.iter().copied().collect();
+    let listed_slots: Vec<(u64, bool)> = if show_skipped {
+        (start_slot..=end_slot).map(|slot| (slot, !produced.contains(&slot))).collect()
+    } else {
+        produced_slots.iter().map(|slot| (*slot, false)).collect()
+    };
+
+    match cli.format {
+        OutputFormat::Json if show_skipped => {
+            #[derive(Serialize)]
+            struct ListedSlot {
+                slot: u64,
+                skipped: bool,
+            }
+
+            let slots: Vec<ListedSlot> = listed_slots
+                .iter()
+                .map(|(slot, skipped)| ListedSlot { slot: *slot, skipped: *skipped })
+                .collect();
+            println!("{}", serde_json::to_string(&slots).expect("slot list serializes to JSON"));
+        }
+        OutputFormat::Json => println!("{}", serde_json::json!(produced_slots)),
+        _ => {
+            for (slot, skipped) in listed_slots {
+                if skipped {
+                    println!("{} skipped", slot);
+                } else {
+                    println!("{}", slot);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//Function that fetches a single block and saves it as a one-record file, so it can be scanned again offline
+fn run_fixture_capture(client: &impl BlockSource, slot: u64, path: &Path) -> Result<(), TpsError> {
+    let block = client.get_block(slot)?;
 
-    let duration = calculation_end.signed_duration_since(calculation_start).to_std().unwrap();
+    BlockDump::create(path)?.write_block(slot, &block)?;
+    log::info!("Saved the block in slot {} with {} transactions to {}", slot, block.transactions.len(), path.display());
 
-    log::info!("Calculation took: {} seconds", duration.as_secs());
-    log::info!("Total transactions per second over period: {}", transactions_per_second);
+    Ok(())
+}
+
+//Function that runs the command line tool once the options are parsed
+fn run(cli: &Cli) -> Result<(), TpsError> {
+    if cli.print_schema {
+        println!("{}", report::render_json_schema());
+        return Ok(());
+    }
+
+    if let Some(template) = &cli.template {
+        report::check_template(template)?;
+    }
+
+    if let Some(fixture_capture) = &cli.fixture_capture {
+        let slot = match fixture_capture[0].parse::<u64>() {
+            Ok(slot) => slot,
+            Err(err) => {
+                Cli::command()
+                    .error(ErrorKind::ValueValidation, format!("invalid slot '{}': {}", fixture_capture[0], err))
+                    .exit()
+            }
+        };
+        let client = build_client(&cli.rpc_url[0], &cli.user_agent, cli.commitment, None);
+        let client = RetryingBlockSource::new(client, cli.rpc_retries);
+        return run_fixture_capture(&client, slot, Path::new(&fixture_capture[1]));
+    }
+
+    let phase_totals = if cli.trace_timing || cli.profile {
+        let (timing_layer, phase_totals) = TimingLayer::new();
+        let subscriber = tracing_subscriber::registry().with(timing_layer);
+        tracing::subscriber::set_global_default(subscriber).unwrap();
+        Some(phase_totals)
+    } else {
+        None
+    };
+
+    match (&cli.command, &cli.from_file) {
+        (Some(Command::Batch { inputs, jobs }), _) => run_batch(cli, inputs, *jobs)?,
+        (Some(Command::Blocks { start, end, recent, show_skipped }), _) => {
+            let concurrency_limit = cli.max_concurrent_rpc.map(|permits| Arc::new(Semaphore::new(permits as usize)));
+            let client = build_client(&cli.rpc_url[0], &cli.user_agent, cli.commitment, concurrency_limit.as_ref());
+            let client = RetryingBlockSource::new(client, cli.rpc_retries);
+            run_blocks(&client, cli, *start, *end, *recent, *show_skipped)?;
+        }
+        (Some(Command::Healthcheck), _) => run_healthcheck(cli)?,
+        (Some(Command::Replay { input }), _) => {
+            log::info!("Replaying blocks from {}", input.display());
+
+            let source = FileBlockSource::open(input)?;
+            scan_and_print(&source, cli, None)?;
+        }
+        (None, Some(from_file)) => {
+            log::info!("Loading blocks from {}", from_file.display());
+
+            let source = FileBlockSource::open(from_file)?;
+            scan_and_print(&source, cli, None)?;
+        }
+        #[cfg(feature = "geyser")]
+        (None, None) if cli.geyser_url.is_some() => {
+            let geyser_url = cli.geyser_url.as_deref().unwrap();
+            log::info!("Streaming blocks from {}", geyser_url);
+
+            let window = match cli.last_slots {
+                Some(block_count) => ScanWindow::LastBlocks(block_count),
+                None => ScanWindow::Seconds(cli.window_seconds),
+            };
+            let capacity = geyser::capacity_for_window(window, cli.tip_lag, cli.warmup);
+            let x_token = cli.geyser_x_token.as_deref();
+            let source = StreamedBlockSource::subscribe(geyser_url, x_token, cli.commitment, capacity)?;
+
+            //The stream only holds blocks produced since it started, so the window has to fill up first
+            log::info!("Waiting for the stream to cover the window");
+            source.wait_for_window(window, cli.tip_lag, cli.warmup, geyser::STREAM_TIMEOUT)?;
+            scan_and_print(&*source, cli, None)?;
+        }
+        (None, None) => {
+            log::info!("Solana count transactions per second!");
+
+            let concurrency_limit = cli.max_concurrent_rpc.map(|permits| Arc::new(Semaphore::new(permits as usize)));
+            let clients: Vec<RpcClient> = cli.rpc_url
+                .iter()
+                .map(|url| build_client(url, &cli.user_agent, cli.commitment, concurrency_limit.as_ref()))
+                .collect();
+            let client = &clients[0];
+
+            //The node version is only context for the log, so a node that can't report it doesn't stop the scan
+            match client.get_version() {
+                Ok(version) => log::info!("Solana version: {}", &version.solana_core),
+                Err(err) => log::warn!("Could not fetch node version: {}", err),
+            }
+
+            let pool = endpoint_pool(cli, &clients);
+
+            if cli.dry_run {
+                //Through the pool the scan would use, so its endpoints and rate limit count toward the estimate
+                let estimate = match &pool {
+                    Some(pool) => scan::estimate_scan_cost(pool, cli.window_seconds)?,
+                    None => scan::estimate_scan_cost(client, cli.window_seconds)?,
+                };
+
+                log::info!("Dry run, no scan performed");
+                log::info!("Estimated blocks to scan: {}", estimate.estimated_blocks);
+                log::info!("Estimated RPC calls: {}", estimate.estimated_calls);
+                log::info!("Estimated scan time: {:.1} seconds", estimate.estimated_seconds);
+                return Ok(());
+            }
+
+            if !cli.no_preflight {
+                for (url, client) in cli.rpc_url.iter().zip(&clients) {
+                    match scan::check_node_health(client, url) {
+                        Ok(()) => log::debug!("Endpoint {} is healthy", url),
+                        Err(err @ TpsError::NodeUnhealthy { .. }) => {
+                            log::error!("Not scanning, the numbers would be off. Pass --no-preflight to scan anyway");
+                            return Err(err);
+                        }
+                        //Some providers don't serve getHealth, which says nothing about the node being behind
+                        Err(err) => log::warn!("Could not check the health of endpoint {}: {}", url, err),
+                    }
+                }
+            }
+
+            if cli.follow {
+                let options = scan_options(cli)?;
+                let poll_interval = Duration::from_millis(cli.follow_interval_ms);
+                let client = RetryingBlockSource::new(client, cli.rpc_retries);
+                return follow::follow(&client, &options.analysis, cli.tip_lag, poll_interval, |summary| {
+                    println!("{}", summary);
+                });
+            }
+
+            let official = if cli.compare_to_official {
+                //With several windows the node's samples cover the widest one, which the scan covers too
+                let window_seconds = cli.windows.iter().copied().max().unwrap_or(cli.window_seconds);
+                Some(scan::fetch_official_tps(client, window_seconds)?)
+            } else {
+                None
+            };
+
+            match &pool {
+                Some(pool) => {
+                    scan_rpc(pool, cli, official)?;
+
+                    if clients.len() > 1 {
+                        for (url, requests) in pool.request_counts() {
+                            log::info!("Endpoint {}: {} requests", url, requests);
+                        }
+                    }
+                }
+                None => scan_rpc(client, cli, official)?,
+            }
+        }
+    }
+
+    if let Some(phase_totals) = &phase_totals {
+        if cli.trace_timing {
+            timing::log_phase_totals(phase_totals);
+        }
+
+        //On stderr, so it shows without a log level and stays out of JSON or Prometheus output on stdout
+        if cli.profile {
+            eprintln!("{}", timing::format_profile(phase_totals));
+        }
+    }
+
+    Ok(())
+}
+
+//Function that makes a panic log an error naming the slot that was being processed, instead of the default
+//message, and exit with its own code so bug reports say where it happened
+fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        let payload = info.payload();
+        let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+            (Some(message), _) => message.to_string(),
+            (None, Some(message)) => message.clone(),
+            (None, None) => "unknown panic".to_string(),
+        };
+        let location = match info.location() {
+            Some(location) => format!("{}:{}", location.file(), location.line()),
+            None => "unknown location".to_string(),
+        };
+
+        match error::current_slot() {
+            Some(slot) => log::error!("Internal error while processing slot {}: {} at {}", slot, message, location),
+            None => log::error!("Internal error: {} at {}", message, location),
+        }
+        process::exit(EXIT_PANIC);
+    }));
 }
 
 fn main() {
     dotenv().ok();
     env_logger::init();
 
-    log::info!("Solana count transactions per second!");
+    let cli = Cli::parse();
 
-    let client = RpcClient::new("https://api.devnet.solana.com");
+    if cli.panic_as_error {
+        install_panic_hook();
+    }
 
-    let solana_version = client.get_version().unwrap().solana_core;
-    log::info!("Solana version: {}", &solana_version);
-    calculate_for_range(&client, 60 * 5);
+    if let Err(err) = run(&cli) {
+        log::error!("{}", err);
+        process::exit(err.exit_code());
+    }
 }
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use serde_json::{ json, Value };
+use solana_sdk::pubkey::Pubkey;
+
+use crate::analysis::{ calculate_tps, BlockAnalysis, RewardTotals };
+
+//Most programs the programs metric lists
+const TOP_METRIC_PROGRAMS: usize = 10;
+
+//A number worked out over the blocks of a window. Every counted block is observed once, in walk order, as it
+//is counted, and the metric then sums up what it saw over the span the window turned out to cover. Summing up
+//leaves the metric as it was, so a partial report can be made halfway. Adding a metric only takes a new
+//implementation and kind.
+//Metrics sit next to the report's own totals rather than replacing them: every scan still sums up the fields
+//of TpsReport, which the output formats, templates and history all read, and only the metrics asked for are
+//kept on top of those under the report's metrics
+pub trait Metric {
+    fn observe(&mut self, block_time: i64, analysis: &BlockAnalysis);
+
+    fn finish(&self, window: MetricWindow) -> Value;
+}
+
+//Metrics that can be asked for with --metrics
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MetricKind {
+    //Counted transactions and how many per second
+    Tps,
+    //Fees paid, split into base and priority fees
+    Fees,
+    //Compute units consumed by user transactions and how many per second
+    Cu,
+    //Block rewards by reward type
+    Rewards,
+    //User transactions per program, for the busiest programs
+    Programs,
+}
+
+//Block times a window spans, so metrics measure over the same span as the report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricWindow {
+    pub oldest_timestamp: i64,
+    pub newest_timestamp: i64,
+}
+
+impl MetricKind {
+    //Name the metric is reported under
+    pub fn name(self) -> &'static str {
+        match self {
+            MetricKind::Tps => "tps",
+            MetricKind::Fees => "fees",
+            MetricKind::Cu => "cu",
+            MetricKind::Rewards => "rewards",
+            MetricKind::Programs => "programs",
+        }
+    }
+
+    //Function that creates the metric, ready to observe blocks. Without full detail votes can't be told apart,
+    //so metrics counting transactions count all of them
+    pub fn build(self, full_detail: bool) -> Box<dyn Metric> {
+        match self {
+            MetricKind::Tps => Box::new(TpsMetric { full_detail, transactions: 0 }),
+            MetricKind::Fees => Box::new(FeesMetric::default()),
+            MetricKind::Cu => Box::new(ComputeUnitsMetric::default()),
+            MetricKind::Rewards => Box::new(RewardsMetric::default()),
+            MetricKind::Programs => Box::new(ProgramsMetric::default()),
+        }
+    }
+}
+
+struct TpsMetric {
+    full_detail: bool,
+    transactions: u64,
+}
+
+impl Metric for TpsMetric {
+    fn observe(&mut self, _block_time: i64, analysis: &BlockAnalysis) {
+        let counted = if self.full_detail { analysis.user_transactions } else { analysis.total_transactions };
+        self.transactions += counted;
+    }
+
+    fn finish(&self, window: MetricWindow) -> Value {
+        json!({
+            "transactions": self.transactions,
+            "transactions_per_second": calculate_tps(
+                window.oldest_timestamp,
+                window.newest_timestamp,
+                self.transactions
+            ),
+        })
+    }
+}
+
+#[derive(Default)]
+struct FeesMetric {
+    fees: u64,
+    base_fees: u64,
+    priority_fees: u64,
+}
+
+impl Metric for FeesMetric {
+    fn observe(&mut self, _block_time: i64, analysis: &BlockAnalysis) {
+        self.fees = self.fees.saturating_add(analysis.fees);
+        self.base_fees = self.base_fees.saturating_add(analysis.base_fees);
+        self.priority_fees = self.priority_fees.saturating_add(analysis.priority_fees);
+    }
+
+    fn finish(&self, _window: MetricWindow) -> Value {
+        json!({
+            "fees": self.fees,
+            "base_fees": self.base_fees,
+            "priority_fees": self.priority_fees,
+        })
+    }
+}
+
+#[derive(Default)]
+struct ComputeUnitsMetric {
+    compute_units: u64,
+}
+
+impl Metric for ComputeUnitsMetric {
+    fn observe(&mut self, _block_time: i64, analysis: &BlockAnalysis) {
+        self.compute_units = self.compute_units.saturating_add(analysis.compute_units);
+    }
+
+    fn finish(&self, window: MetricWindow) -> Value {
+        json!({
+            "compute_units": self.compute_units,
+            "compute_units_per_second": calculate_tps(
+                window.oldest_timestamp,
+                window.newest_timestamp,
+                self.compute_units
+            ),
+        })
+    }
+}
+
+#[derive(Default)]
+struct RewardsMetric {
+    rewards: RewardTotals,
+}
+
+impl Metric for RewardsMetric {
+    fn observe(&mut self, _block_time: i64, analysis: &BlockAnalysis) {
+        self.rewards.add(&analysis.rewards);
+    }
+
+    fn finish(&self, _window: MetricWindow) -> Value {
+        let mut rewards = serde_json::to_value(self.rewards).expect("reward totals serialize to JSON");
+        rewards["total"] = json!(self.rewards.total());
+        rewards
+    }
+}
+
+#[derive(Default)]
+struct ProgramsMetric {
+    program_transaction_counts: HashMap<Pubkey, u64>,
+}
+
+impl Metric for ProgramsMetric {
+    fn observe(&mut self, _block_time: i64, analysis: &BlockAnalysis) {
+        for (program_id, count) in &analysis.program_transaction_counts {
+            *self.program_transaction_counts.entry(*program_id).or_default() += count;
+        }
+    }
+
+    //Busiest programs first, ties broken by program id so the order doesn't change between runs
+    fn finish(&self, _window: MetricWindow) -> Value {
+        let mut counts: Vec<(Pubkey, u64)> = self.program_transaction_counts
+            .iter()
+            .map(|(program_id, count)| (*program_id, *count))
+            .collect();
+        counts.sort_by(|(a_id, a_count), (b_id, b_count)| b_count.cmp(a_count).then(a_id.cmp(b_id)));
+
+        counts
+            .into_iter()
+            .take(TOP_METRIC_PROGRAMS)
+            .map(|(program_id, count)| json!({ "program_id": program_id.to_string(), "transactions": count }))
+            .collect()
+    }
+}
@@ -0,0 +1,311 @@
+use std::sync::atomic::{ AtomicU64, AtomicUsize, Ordering };
+use std::sync::{ Mutex, MutexGuard };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use clap::ValueEnum;
+use solana_transaction_status::EncodedConfirmedBlock;
+
+use crate::error::{ is_transient, TpsError };
+use crate::source::{ BlockSource, PerformanceSamples, TransactionDetailLevel };
+
+//How requests are spread over several endpoints
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Balance {
+    //Send every request to the first endpoint
+    #[default]
+    First,
+    //Send every block request to the next endpoint in turn, so each one sees a share of the load
+    RoundRobin,
+}
+
+//How long an endpoint that failed with a transient error is left out of the rotation
+const DOWN_COOLDOWN: Duration = Duration::from_secs(30);
+
+//When an endpoint may next be sent a request, and until when it is left out of the rotation after failing
+#[derive(Debug, Default)]
+struct EndpointState {
+    next_request_at: Option<Instant>,
+    down_until: Option<Instant>,
+}
+
+//Block source that rotates through several equal endpoints for every block request, to stay under each one's
+//rate limit. The tip and the first available block always come from the first endpoint, so a scan sees one
+//consistent view of where the chain starts and ends. A block request that fails with a transient error is
+//sent to the next endpoint, and the one that failed sits out the rotation for a while
+pub struct RoundRobinBlockSource<S> {
+    endpoints: Vec<(String, S)>,
+    next: AtomicUsize,
+    requests: Vec<AtomicU64>,
+    states: Vec<Mutex<EndpointState>>,
+    //Shortest time between two requests to the same endpoint, None when endpoints aren't rate limited
+    min_request_interval: Option<Duration>,
+}
+
+impl<S: BlockSource> RoundRobinBlockSource<S> {
+    //Function that creates the pool over the given endpoints, each with a name used when reporting on it
+    pub fn new(endpoints: Vec<(String, S)>) -> Self {
+        assert!(!endpoints.is_empty(), "an endpoint pool needs at least one endpoint");
+
+        let requests = endpoints.iter().map(|_| AtomicU64::new(0)).collect();
+        let states = endpoints.iter().map(|_| Mutex::default()).collect();
+        RoundRobinBlockSource {
+            endpoints,
+            next: AtomicUsize::new(0),
+            requests,
+            states,
+            min_request_interval: None,
+        }
+    }
+
+    //Function that holds each endpoint to at most the given number of requests per second. Requests that would
+    //go over wait for their turn
+    pub fn with_rate_limit(mut self, requests_per_second: u32) -> Self {
+        self.min_request_interval = Some(Duration::from_secs(1) / requests_per_second.max(1));
+        self
+    }
+
+    //Function that returns how many requests each endpoint has served so far, in the order they were given
+    pub fn request_counts(&self) -> Vec<(&str, u64)> {
+        self.endpoints
+            .iter()
+            .zip(&self.requests)
+            .map(|((name, _), requests)| (name.as_str(), requests.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    fn first(&self) -> &S {
+        self.request(0)
+    }
+
+    //Function that sends a request to the endpoints in turn, skipping those that recently went down. A transient
+    //failure takes the endpoint out of the rotation and passes the request on, until every endpoint was tried
+    fn rotate<T>(&self, request: impl Fn(&S) -> Result<T, TpsError>) -> Result<T, TpsError> {
+        let mut last_err = None;
+
+        for _ in 0..self.endpoints.len() {
+            let index = self.next_up();
+            match request(self.request(index)) {
+                Err(TpsError::Rpc(err)) if is_transient(&err) => {
+                    log::warn!(
+                        "Endpoint {} failed, leaving it out for {} seconds: {}",
+                        self.endpoints[index].0,
+                        DOWN_COOLDOWN.as_secs(),
+                        err
+                    );
+                    self.state(index).down_until = Some(Instant::now() + DOWN_COOLDOWN);
+                    last_err = Some(TpsError::Rpc(err));
+                }
+                result => {
+                    return result;
+                }
+            }
+        }
+
+        Err(last_err.expect("every endpoint was tried"))
+    }
+
+    //Function that returns the index of the next endpoint in the rotation that isn't down. When all of them are,
+    //the next one is tried anyway, as one of them may well be back
+    fn next_up(&self) -> usize {
+        let now = Instant::now();
+        let mut index = 0;
+
+        for _ in 0..self.endpoints.len() {
+            index = self.next.fetch_add(1, Ordering::Relaxed) % self.endpoints.len();
+            if self.state(index).down_until.is_none_or(|down_until| down_until <= now) {
+                return index;
+            }
+        }
+
+        index
+    }
+
+    //Function that counts a request to the endpoint, once it is its turn under the rate limit
+    fn request(&self, index: usize) -> &S {
+        if let Some(min_request_interval) = self.min_request_interval {
+            let wait = {
+                let mut state = self.state(index);
+                let now = Instant::now();
+                let request_at = state.next_request_at.map_or(now, |next_request_at| next_request_at.max(now));
+                state.next_request_at = Some(request_at + min_request_interval);
+                request_at - now
+            };
+            if !wait.is_zero() {
+                thread::sleep(wait);
+            }
+        }
+
+        self.requests[index].fetch_add(1, Ordering::Relaxed);
+        &self.endpoints[index].1
+    }
+
+    fn state(&self, index: usize) -> MutexGuard<'_, EndpointState> {
+        self.states[index].lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<S: BlockSource> BlockSource for RoundRobinBlockSource<S> {
+    fn get_slot(&self) -> Result<u64, TpsError> {
+        self.first().get_slot()
+    }
+
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+        self.rotate(|endpoint| endpoint.get_block(slot))
+    }
+
+    fn get_block_with_details(
+        &self,
+        slot: u64,
+        details: TransactionDetailLevel,
+        rewards: bool
+    ) -> Result<(EncodedConfirmedBlock, Option<u64>), TpsError> {
+        self.rotate(|endpoint| endpoint.get_block_with_details(slot, details, rewards))
+    }
+
+    fn get_block_time(&self, slot: u64) -> Result<Option<i64>, TpsError> {
+        self.rotate(|endpoint| endpoint.get_block_time(slot))
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        self.rotate(|endpoint| endpoint.get_blocks(start_slot, end_slot))
+    }
+
+    fn get_first_available_block(&self) -> Result<u64, TpsError> {
+        self.first().get_first_available_block()
+    }
+}
+
+//The cluster's slot rate comes from the first endpoint, like the tip. Every endpoint in the rotation adds its own
+//share of requests under the rate limit
+impl<S: BlockSource + PerformanceSamples> PerformanceSamples for RoundRobinBlockSource<S> {
+    fn recent_slots_per_second(&self) -> Result<f64, TpsError> {
+        self.first().recent_slots_per_second()
+    }
+
+    fn max_requests_per_second(&self) -> Option<f64> {
+        self.min_request_interval.map(|min_request_interval| {
+            (self.endpoints.len() as f64) / min_request_interval.as_secs_f64()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::FileBlockSource;
+    use crate::scan;
+    use crate::testutil::{ chain, rpc_response_error, source };
+
+    //Endpoint serving the same few blocks as every other one, or failing every request while it is down
+    struct Endpoint {
+        blocks: FileBlockSource,
+        down: bool,
+    }
+
+    impl BlockSource for Endpoint {
+        fn get_slot(&self) -> Result<u64, TpsError> {
+            self.blocks.get_slot()
+        }
+
+        fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+            if self.down {
+                return Err(rpc_response_error(-32005, "Node is behind by 42 slots").into());
+            }
+            self.blocks.get_block(slot)
+        }
+
+        fn get_first_available_block(&self) -> Result<u64, TpsError> {
+            self.blocks.get_first_available_block()
+        }
+    }
+
+    impl PerformanceSamples for Endpoint {
+        fn recent_slots_per_second(&self) -> Result<f64, TpsError> {
+            Ok(2.5)
+        }
+    }
+
+    fn pool(down: &[bool]) -> RoundRobinBlockSource<Endpoint> {
+        let endpoints = down
+            .iter()
+            .enumerate()
+            .map(|(index, down)| {
+                let blocks = source(chain(4, 1_000));
+                (format!("endpoint-{}", index), Endpoint { blocks, down: *down })
+            })
+            .collect();
+
+        RoundRobinBlockSource::new(endpoints)
+    }
+
+    #[test]
+    fn block_requests_rotate_through_the_endpoints_in_order() {
+        let pool = pool(&[false, false, false]);
+
+        for slot in 0..6 {
+            pool.get_block(slot % 4).unwrap();
+        }
+
+        assert_eq!(pool.request_counts(), vec![("endpoint-0", 2), ("endpoint-1", 2), ("endpoint-2", 2)]);
+    }
+
+    #[test]
+    fn tip_and_first_available_block_come_from_the_first_endpoint() {
+        let pool = pool(&[false, false]);
+
+        assert_eq!(pool.get_slot().unwrap(), 3);
+        assert_eq!(pool.get_first_available_block().unwrap(), 0);
+        assert_eq!(pool.request_counts(), vec![("endpoint-0", 2), ("endpoint-1", 0)]);
+    }
+
+    #[test]
+    fn endpoint_that_goes_down_is_left_out_of_the_rotation() {
+        let pool = pool(&[false, true, false]);
+
+        for slot in 0..4 {
+            assert_eq!(pool.get_block(slot).unwrap().parent_slot, slot.saturating_sub(1));
+        }
+
+        //The failed request went on to the next endpoint, and the one that failed wasn't asked again
+        assert_eq!(pool.request_counts(), vec![("endpoint-0", 2), ("endpoint-1", 1), ("endpoint-2", 2)]);
+    }
+
+    #[test]
+    fn request_fails_once_every_endpoint_is_down() {
+        let pool = pool(&[true, true]);
+
+        assert!(matches!(pool.get_block(1), Err(TpsError::Rpc(_))));
+        assert_eq!(pool.request_counts(), vec![("endpoint-0", 1), ("endpoint-1", 1)]);
+    }
+
+    #[test]
+    fn rate_limit_spaces_out_requests_to_an_endpoint() {
+        let pool = pool(&[false]).with_rate_limit(20);
+        let start = Instant::now();
+
+        for slot in 0..3 {
+            pool.get_block(slot).unwrap();
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(100), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn pool_serves_requests_per_second_for_every_endpoint() {
+        assert_eq!(pool(&[false, false]).max_requests_per_second(), None);
+        assert_eq!(pool(&[false, false]).with_rate_limit(20).max_requests_per_second(), Some(40.0));
+    }
+
+    #[test]
+    fn scan_estimate_is_held_to_the_pool_rate_limit() {
+        let pool = pool(&[false, false]).with_rate_limit(20);
+
+        let estimate = scan::estimate_scan_cost(&pool, 10).unwrap();
+
+        //Every slot of the sampled chain produced a block, so 2.5 slots per second is 25 blocks over the window
+        assert_eq!(estimate.estimated_blocks, 25);
+        assert_eq!(estimate.estimated_calls, 26);
+        assert!(estimate.estimated_seconds >= 26.0 / 40.0, "{}", estimate.estimated_seconds);
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use crate::report::TpsReport;
+
+//Function that appends one gauge, with its help and type lines, in the Prometheus text exposition format.
+//Each sample is a label set, empty for none, and a value
+fn write_gauge(output: &mut String, name: &str, help: &str, samples: &[(String, f64)]) {
+    writeln!(output, "# HELP {} {}", name, help).unwrap();
+    writeln!(output, "# TYPE {} gauge", name).unwrap();
+    for (labels, value) in samples {
+        writeln!(output, "{}{} {}", name, labels, value).unwrap();
+    }
+}
+
+//Function that renders the summary of a scan in the Prometheus text exposition format,
+//ready to be served to a scraper or written for the node exporter's textfile collector
+pub fn render(report: &TpsReport) -> String {
+    render_samples(&[(String::new(), report)])
+}
+
+//Function that renders the summaries of scans over several windows, one sample per window told apart
+//by a window label, as a metric family may only appear once in the output
+pub fn render_windows(reports: &[TpsReport]) -> String {
+    let labelled: Vec<(String, &TpsReport)> = reports
+        .iter()
+        .map(|report| (format!("{{window=\"{}\"}}", report.window), report))
+        .collect();
+
+    render_samples(&labelled)
+}
+
+//Function that renders every gauge with one sample per labelled report
+fn render_samples(reports: &[(String, &TpsReport)]) -> String {
+    let samples = |value: fn(&TpsReport) -> Option<f64>| -> Vec<(String, f64)> {
+        reports
+            .iter()
+            .filter_map(|(labels, report)| value(report).map(|value| (labels.clone(), value)))
+            .collect()
+    };
+    let mut output = String::new();
+
+    write_gauge(
+        &mut output,
+        "solana_user_transactions_per_second",
+        "User transactions per second over the scanned window.",
+        &samples(|report| Some(report.transactions_per_second))
+    );
+    write_gauge(
+        &mut output,
+        "solana_user_transactions",
+        "User transactions in the scanned window.",
+        &samples(|report| Some(report.user_transactions as f64))
+    );
+    //Without user transactions there is no rate, and leaving the sample out is clearer than reporting 0
+    let landed_rates = samples(|report| report.landed_rate);
+    if !landed_rates.is_empty() {
+        write_gauge(
+            &mut output,
+            "solana_landed_rate_ratio",
+            "Share of user transactions in the scanned window that executed without an error.",
+            &landed_rates
+        );
+    }
+    let vote_to_user_ratios = samples(|report| report.vote_to_user_ratio);
+    if !vote_to_user_ratios.is_empty() {
+        write_gauge(
+            &mut output,
+            "solana_vote_to_user_ratio",
+            "Vote transactions per user transaction in the scanned window.",
+            &vote_to_user_ratios
+        );
+    }
+    write_gauge(
+        &mut output,
+        "solana_vote_transactions",
+        "Vote transactions in the scanned window.",
+        &samples(|report| Some(report.vote_transactions as f64))
+    );
+    write_gauge(
+        &mut output,
+        "solana_blocks_scanned",
+        "Blocks scanned in the window.",
+        &samples(|report| Some(report.blocks_scanned as f64))
+    );
+    write_gauge(
+        &mut output,
+        "solana_skip_rate_ratio",
+        "Share of slots in the scanned range that did not produce a block.",
+        &samples(|report| Some(report.skip_rate))
+    );
+    write_gauge(
+        &mut output,
+        "solana_reward_only_blocks",
+        "Blocks in the window without transactions that only paid out rewards.",
+        &samples(|report| Some(report.reward_only_blocks as f64))
+    );
+    write_gauge(
+        &mut output,
+        "solana_latest_slot",
+        "Newest slot included in the scan. A value that stops advancing means the data is stale.",
+        &samples(|report| Some(report.newest_slot as f64))
+    );
+    write_gauge(
+        &mut output,
+        "solana_oldest_scanned_slot",
+        "Oldest slot included in the scan.",
+        &samples(|report| Some(report.oldest_slot as f64))
+    );
+    write_gauge(
+        &mut output,
+        "solana_scan_duration_seconds",
+        "How long the last scan took.",
+        &samples(|report| Some(report.calculation_duration.as_secs_f64()))
+    );
+
+    output
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ self, Write };
+use std::path::Path;
+
+use serde::{ Deserialize, Serialize };
+use solana_transaction_status::EncodedConfirmedBlock;
+
+use crate::error::TpsError;
+use crate::source::{ clone_block, BlockSource };
+
+//Version of the per-block record format. Bumped whenever a record's fields change meaning
+pub const BLOCK_RECORD_VERSION: u32 = 1;
+
+//One line of a per-block NDJSON file: a block exactly as the RPC endpoint returned it, with its slot
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockRecord {
+    pub version: u32,
+    pub slot: u64,
+    pub block: EncodedConfirmedBlock,
+}
+
+//Same layout as BlockRecord, borrowing the block so it can be written without a copy
+#[derive(Serialize)]
+struct BlockRecordRef<'a> {
+    version: u32,
+    slot: u64,
+    block: &'a EncodedConfirmedBlock,
+}
+
+//Function that writes a block as one NDJSON record line, readable by read_block_records
+pub fn write_block_record(
+    writer: &mut impl Write,
+    slot: u64,
+    block: &EncodedConfirmedBlock
+) -> io::Result<()> {
+    let record = BlockRecordRef {
+        version: BLOCK_RECORD_VERSION,
+        slot,
+        block,
+    };
+    serde_json::to_writer(&mut *writer, &record)?;
+    writeln!(writer)
+}
+
+//Function that rejects a record written in another version of the format
+fn check_record_version(record: &BlockRecord, line_number: usize) -> Result<(), TpsError> {
+    if record.version != BLOCK_RECORD_VERSION {
+        return Err(TpsError::InvalidRecord {
+            line: line_number,
+            reason: format!(
+                "record version {} is not the supported version {}",
+                record.version,
+                BLOCK_RECORD_VERSION
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+//Function that reads every record of a per-block file, rejecting records that don't match the format.
+//The file is either NDJSON with one record per line, or a single JSON array of records
+pub fn read_block_records(path: &Path) -> Result<Vec<BlockRecord>, TpsError> {
+    let contents = fs::read_to_string(path)?;
+
+    if contents.trim_start().starts_with('[') {
+        return parse_block_record_array(&contents);
+    }
+
+    let mut records = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: BlockRecord = serde_json::from_str(line).map_err(|err| {
+            TpsError::InvalidRecord {
+                line: line_number,
+                reason: err.to_string(),
+            }
+        })?;
+
+        check_record_version(&record, line_number)?;
+
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+//Function that parses a JSON array of records. Errors point at the line the parser stopped on
+fn parse_block_record_array(contents: &str) -> Result<Vec<BlockRecord>, TpsError> {
+    let records: Vec<BlockRecord> = serde_json::from_str(contents).map_err(|err| {
+        TpsError::InvalidRecord {
+            line: err.line(),
+            reason: err.to_string(),
+        }
+    })?;
+
+    for record in &records {
+        check_record_version(record, 0)?;
+    }
+
+    Ok(records)
+}
+
+//Block source backed by records read from a file, for analysis without an RPC endpoint.
+//The newest record plays the chain tip and the oldest one the first available block
+pub struct FileBlockSource {
+    blocks: BTreeMap<u64, EncodedConfirmedBlock>,
+}
+
+impl FileBlockSource {
+    pub fn from_records(records: Vec<BlockRecord>) -> Result<Self, TpsError> {
+        let mut blocks = BTreeMap::new();
+
+        for record in records {
+            if blocks.insert(record.slot, record.block).is_some() {
+                return Err(TpsError::InvalidRecord {
+                    line: 0,
+                    reason: format!("slot {} appears more than once", record.slot),
+                });
+            }
+        }
+
+        if blocks.is_empty() {
+            return Err(TpsError::InvalidRecord {
+                line: 0,
+                reason: "the file holds no block records".to_string(),
+            });
+        }
+
+        Ok(FileBlockSource { blocks })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, TpsError> {
+        Self::from_records(read_block_records(path)?)
+    }
+}
+
+impl BlockSource for FileBlockSource {
+    fn get_slot(&self) -> Result<u64, TpsError> {
+        Ok(*self.blocks.keys().next_back().expect("source holds at least one block"))
+    }
+
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+        self.blocks.get(&slot).map(clone_block).ok_or(TpsError::NoBlockAvailable(slot))
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        Ok(self.blocks.range(start_slot..=end_slot).map(|(slot, _)| *slot).collect())
+    }
+
+    fn get_first_available_block(&self) -> Result<u64, TpsError> {
+        Ok(*self.blocks.keys().next().expect("source holds at least one block"))
+    }
+}
@@ -0,0 +1,1165 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{ Serialize, Serializer };
+use solana_sdk::{ native_token::lamports_to_sol, pubkey::Pubkey };
+
+use crate::analysis::{
+    tps_from_span,
+    FeeDistribution,
+    LargeTransfer,
+    RewardTotals,
+    SizeDistribution,
+    TpsAverageMethod,
+    TpsBucket,
+    NOMINAL_SLOT_SECONDS,
+};
+use crate::error::TpsError;
+use crate::scan::ScanWindow;
+use crate::source::TransactionDetailLevel;
+use crate::timing::format_duration;
+
+//Ways the summary of a scan can be printed
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    //Log lines, as printed by earlier versions
+    Text,
+    //An aligned key/value table on stdout
+    Table,
+    //Prometheus text exposition format on stdout
+    Prometheus,
+    //The report as a JSON object on stdout
+    Json,
+}
+
+//Unit TPS figures are displayed in. Only the printed numbers are scaled, the report keeps raw TPS
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TpsUnit {
+    //Transactions per second
+    #[default]
+    Tps,
+    //Thousands of transactions per second, easier to read on busy clusters like mainnet
+    Ktps,
+}
+
+impl TpsUnit {
+    pub fn scale(self, transactions_per_second: f64) -> f64 {
+        match self {
+            TpsUnit::Tps => transactions_per_second,
+            TpsUnit::Ktps => transactions_per_second / 1000.0,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TpsUnit::Tps => "TPS",
+            TpsUnit::Ktps => "kTPS",
+        }
+    }
+}
+
+//Version of the report's JSON shape. Adding a field keeps the version, but removing or renaming one,
+//or changing what an existing field means, bumps it, so consumers can refuse shapes they don't know.
+//Version 2 made rewards null for blocks fetched without them
+pub const REPORT_SCHEMA_VERSION: u32 = 2;
+
+//Summary of a scan over a window of blocks. Its serialized form is versioned by schema_version,
+//following the policy described at REPORT_SCHEMA_VERSION
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct TpsReport {
+    pub schema_version: u32,
+    pub window: ScanWindow,
+    //User transactions per second, or all transactions per second when blocks were fetched without full detail
+    pub transactions_per_second: f64,
+    //Unit the TPS figures are printed in. All TPS values in the report itself stay in transactions per second
+    pub unit: TpsUnit,
+    //How transactions_per_second was averaged over the window
+    pub tps_average_method: TpsAverageMethod,
+    //Experimental alternative to TPS that counts distinct fee payers per block instead of user transactions,
+    //so spam from a few payers doesn't inflate it. Only reported when asked for
+    pub weighted_transactions_per_second: Option<f64>,
+    //TPS without the newest and oldest counted block, which only partly overlap the window. Only reported when
+    //asked for, and None when too few blocks were counted to trim
+    pub trimmed_transactions_per_second: Option<f64>,
+    //Metrics asked for with --metrics, by name, each in its own shape. Empty when none were asked for
+    pub metrics: BTreeMap<String, serde_json::Value>,
+    pub total_transactions: u64,
+    pub user_transactions: u64,
+    //Transactions only calling infrastructure programs, the vote program unless configured otherwise.
+    //Zero when blocks were fetched without full detail, as votes can't be recognized then
+    pub vote_transactions: u64,
+    //How much transaction detail blocks were fetched with, which decides what the counts mean
+    pub transaction_details: TransactionDetailLevel,
+    //Transactions without any instruction, counted as neither vote nor user transactions
+    pub empty_transactions: u64,
+    //User transactions left out by the program filter, so not part of the user transaction numbers
+    pub filtered_out: u64,
+    pub successful_user_transactions: u64,
+    //Share of user transactions that executed without an error, None when there were no user transactions.
+    //It drops sharply when the network is congested
+    pub landed_rate: Option<f64>,
+    //Vote transactions per user transaction, None when there were no user transactions. A ratio that climbs
+    //means user activity is dropping while validators keep voting
+    pub vote_to_user_ratio: Option<f64>,
+    //User transactions that only call the system program, a rough count of plain payments
+    pub system_program_transactions: u64,
+    //User transactions using a durable nonce instead of a recent blockhash, typical for offline signing
+    pub durable_nonce_transactions: u64,
+    //User transactions requiring more than one signature, and the signatures a user transaction requires on
+    //average. The average is None without user transactions
+    pub multisig_transactions: u64,
+    pub average_signatures: Option<f64>,
+    //System program transfers of at least the threshold, in lamports, when one was set. Only the largest are
+    //listed, the elided count says how many more there were
+    pub large_transfer_threshold: Option<u64>,
+    pub large_transfer_count: u64,
+    pub large_transfers: Vec<LargeTransfer>,
+    pub elided_large_transfers: u64,
+    //User transactions that loaded accounts from address lookup tables, a measure of v0 transaction adoption
+    pub lookup_table_transactions: u64,
+    //Top-level instructions of the user transactions, and how many were processed per second
+    pub total_instructions: u64,
+    pub instructions_per_second: f64,
+    //Compute units the user transactions consumed, and how many per second. A measure of load that, unlike TPS,
+    //weighs every transaction by its cost. Transactions without recorded compute units are left out
+    pub compute_units: u64,
+    pub compute_units_per_second: f64,
+    //Serialized sizes of the user transactions, None when there were none
+    pub transaction_sizes: Option<SizeDistribution>,
+    pub total_fees: u64,
+    //Fees paid by the user transactions with status metadata, None when there were none
+    pub fee_distribution: Option<FeeDistribution>,
+    //Fees paid by transactions that executed successfully, and fees spent on transactions that failed
+    pub fees_from_successful: u64,
+    pub fees_from_failed: u64,
+    //Fees split into the base fee charged per signature and the priority fee paid on top of it
+    pub base_fees: u64,
+    pub priority_fees: u64,
+    //Estimated lamports burned, half of the base fees. Priority fees all go to the leader
+    pub burned_fees: u64,
+    //Block rewards paid out over the window, by reward type, in lamports. None when blocks came without them
+    pub rewards: Option<RewardTotals>,
+    //Number of instructions invoking each program, across all scanned transactions
+    #[serde(serialize_with = "serialize_program_counts")]
+    #[schemars(with = "BTreeMap<String, u64>")]
+    pub instruction_counts: HashMap<Pubkey, u64>,
+    //Number of user transactions invoking each program at least once
+    #[serde(serialize_with = "serialize_program_counts")]
+    #[schemars(with = "BTreeMap<String, u64>")]
+    pub program_transaction_counts: HashMap<Pubkey, u64>,
+    //Number of counted user transactions invoking each program the count was restricted to, including those
+    //with none. Empty when every program counts
+    #[serde(serialize_with = "serialize_program_counts")]
+    #[schemars(with = "BTreeMap<String, u64>")]
+    pub listed_program_counts: HashMap<Pubkey, u64>,
+    //Programs that drove the most user transactions per second, busiest first
+    pub top_programs_by_tps: Vec<ProgramTps>,
+    //Fee payers that paid for the most user transactions, busiest first, when asked for with --top-signers.
+    //On windows with too many payers to track the counts are lower bounds, and approximate is set
+    pub top_signers: Vec<SignerCount>,
+    pub top_signers_approximate: bool,
+    //Program whose user transactions are counted per instruction discriminator, if one was given
+    #[serde(serialize_with = "serialize_optional_pubkey")]
+    #[schemars(with = "Option<String>")]
+    pub discriminator_program: Option<Pubkey>,
+    //Number of user transactions calling the discriminator program with each discriminator, keyed by hex
+    #[serde(serialize_with = "serialize_sorted_counts")]
+    pub discriminator_counts: HashMap<String, u64>,
+    //Top-level instructions of the user transactions matching each instruction given with --count-instruction,
+    //keyed by program:discriminator
+    pub counted_instruction_counts: BTreeMap<String, u64>,
+    //Whether blocks were analyzed in parallel, and on how many threads. Sequential counting uses one
+    pub parallel_counting: bool,
+    pub counting_threads: usize,
+    pub blocks_scanned: u64,
+    pub skipped_slots: u64,
+    pub skip_rate: f64,
+    //Scanned blocks without transactions that only paid out rewards. They are among the blocks scanned
+    pub reward_only_blocks: u64,
+    //Tip the walk started behind, None when it started from a seed slot. newest_slot is the block actually used
+    pub tip_slot: Option<u64>,
+    //Blocks nearest the start of the walk that were left out of the window
+    pub warmup_blocks: u64,
+    //Slots of the newest and oldest scanned block, to look the window up in an explorer
+    pub newest_slot: u64,
+    pub oldest_slot: u64,
+    //Slot of the block oldest_timestamp was taken from: the block before the oldest counted one, whose
+    //transactions stay out of the window. For listed slots it is the oldest counted block itself
+    pub boundary_slot: u64,
+    pub slot_span: u64,
+    //Slots between every counted block and its previous one, which the time-weighted average divides by
+    pub counted_slots: u64,
+    pub time_span_seconds: i64,
+    //How much slower, as a fraction, the window's slots ran than the nominal 400 ms, negative when faster.
+    //None when the window spans too few slots to tell
+    pub slot_time_drift: Option<f64>,
+    //Time between consecutive counted blocks, in milliseconds. Irregular slot times show up here even when TPS
+    //looks steady. None with fewer than two counted blocks
+    pub min_block_interval_ms: Option<u64>,
+    pub median_block_interval_ms: Option<u64>,
+    pub max_block_interval_ms: Option<u64>,
+    //Length of the buckets the window was split into, None when it wasn't
+    pub bucket_seconds: Option<u64>,
+    //TPS per bucket, oldest first
+    pub buckets: Vec<TpsBucket>,
+    //Seconds between the oldest and newest scanned block, which is less than asked for when the window is truncated
+    pub window_covered_seconds: i64,
+    //The scan stopped before covering the requested window, e.g. at genesis, a cap, pruning or the maximum runtime
+    pub window_truncated: bool,
+    //The report was written while the scan was still going, and only covers the blocks walked so far
+    pub partial: bool,
+    pub truncated_by_pruning: bool,
+    //The endpoint reported the next older block as cleaned up, a pruning boundary it didn't advertise in advance
+    pub truncated_by_cleanup: bool,
+    //The scan hit its maximum runtime, so the window is only partly covered
+    pub time_limited: bool,
+    pub oldest_timestamp: i64,
+    pub newest_timestamp: i64,
+    #[serde(rename = "calculation_seconds", serialize_with = "serialize_seconds")]
+    #[schemars(with = "f64")]
+    pub calculation_duration: Duration,
+    //TPS the RPC node reported for itself over about the same window, to sanity-check ours against
+    pub official: Option<OfficialTps>,
+}
+
+//TPS from the RPC node's own performance samples. The node counts vote transactions too, only its
+//non-vote figure compares with the user TPS of a scan
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct OfficialTps {
+    pub samples: usize,
+    pub sample_seconds: u64,
+    pub transactions_per_second: f64,
+    //Nodes too old to split out vote transactions don't report it
+    pub non_vote_transactions_per_second: Option<f64>,
+}
+
+//User transactions per second that invoked one program
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ProgramTps {
+    #[serde(serialize_with = "serialize_pubkey")]
+    #[schemars(with = "String")]
+    pub program_id: Pubkey,
+    pub user_transactions: u64,
+    pub transactions_per_second: f64,
+}
+
+//User transactions one fee payer paid for
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SignerCount {
+    #[serde(serialize_with = "serialize_pubkey")]
+    #[schemars(with = "String")]
+    pub fee_payer: Pubkey,
+    pub user_transactions: u64,
+}
+
+//Function that serializes a pubkey as its base58 string
+fn serialize_pubkey<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(pubkey)
+}
+
+//Function that serializes an optional pubkey as its base58 string, or null
+fn serialize_optional_pubkey<S: Serializer>(pubkey: &Option<Pubkey>, serializer: S) -> Result<S::Ok, S::Error> {
+    match pubkey {
+        Some(pubkey) => serializer.collect_str(pubkey),
+        None => serializer.serialize_none(),
+    }
+}
+
+//Function that serializes counts as an object in a stable key order
+fn serialize_sorted_counts<S: Serializer>(counts: &HashMap<String, u64>, serializer: S) -> Result<S::Ok, S::Error> {
+    counts.iter().collect::<BTreeMap<&String, &u64>>().serialize(serializer)
+}
+
+//Function that serializes per-program counts as an object keyed by base58 program id, in a stable order
+fn serialize_program_counts<S: Serializer>(
+    counts: &HashMap<Pubkey, u64>,
+    serializer: S
+) -> Result<S::Ok, S::Error> {
+    counts
+        .iter()
+        .map(|(program_id, count)| (program_id.to_string(), *count))
+        .collect::<BTreeMap<String, u64>>()
+        .serialize(serializer)
+}
+
+//Function that serializes a duration as fractional seconds
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+//How many programs are listed when reporting the most invoked ones
+pub const TOP_PROGRAMS: usize = 10;
+
+//Function that returns the entries with the highest counts, most frequent first
+pub fn top_counts<K: Clone + Ord>(counts: &HashMap<K, u64>, limit: usize) -> Vec<(K, u64)> {
+    let mut top: Vec<(K, u64)> = counts
+        .iter()
+        .map(|(key, count)| (key.clone(), *count))
+        .collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top.truncate(limit);
+    top
+}
+
+//Function that returns the fee payers that paid for the most user transactions, busiest first
+pub fn rank_signers(fee_payer_counts: &HashMap<Pubkey, u64>, limit: usize) -> Vec<SignerCount> {
+    top_counts(fee_payer_counts, limit)
+        .into_iter()
+        .map(|(fee_payer, user_transactions)| SignerCount { fee_payer, user_transactions })
+        .collect()
+}
+
+//Function that ranks programs by the user transactions per second that invoked them over the given span
+pub fn rank_programs_by_tps(
+    program_transaction_counts: &HashMap<Pubkey, u64>,
+    seconds: f64,
+    limit: usize
+) -> Vec<ProgramTps> {
+    top_counts(program_transaction_counts, limit)
+        .into_iter()
+        .map(|(program_id, user_transactions)| ProgramTps {
+            program_id,
+            user_transactions,
+            transactions_per_second: tps_from_span(user_transactions, seconds),
+        })
+        .collect()
+}
+
+//Block characters a sparkline is drawn with, from the lowest value to the highest
+const SPARKLINE_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+//Function that draws the values as a sparkline, one character per value scaled between the smallest and largest.
+//A flat series is drawn at the lowest level, and values that aren't finite show as a space
+pub fn sparkline(values: &[f64]) -> String {
+    let finite = values.iter().copied().filter(|value| value.is_finite());
+    let min = finite.clone().fold(f64::INFINITY, f64::min);
+    let max = finite.fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    values
+        .iter()
+        .map(|value| {
+            if !value.is_finite() {
+                ' '
+            } else if range > 0.0 {
+                let level = ((value - min) / range * ((SPARKLINE_LEVELS.len() - 1) as f64)).round();
+                SPARKLINE_LEVELS[level as usize]
+            } else {
+                SPARKLINE_LEVELS[0]
+            }
+        })
+        .collect()
+}
+
+//Function that prints the report's per-bucket TPS as a sparkline, when the window was split into buckets
+pub fn print_sparkline(report: &TpsReport) {
+    let Some(bucket_seconds) = report.bucket_seconds else {
+        return;
+    };
+    if report.buckets.is_empty() {
+        return;
+    }
+
+    let values: Vec<f64> = report.buckets
+        .iter()
+        .map(|bucket| report.unit.scale(bucket.transactions_per_second))
+        .collect();
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    log::info!(
+        "{} per {} second bucket, oldest first: {} (min {:.2}, max {:.2})",
+        report.unit.label(),
+        bucket_seconds,
+        sparkline(&values),
+        min,
+        max
+    );
+}
+
+//Function that prints every number the TPS figure was worked out from, so it can be checked by hand
+pub fn print_tps_explanation(report: &TpsReport) {
+    let (counted_transactions, counted) = match report.transaction_details {
+        TransactionDetailLevel::Full => (report.user_transactions, "user transactions"),
+        _ => (report.total_transactions, "transactions, votes included as they can't be told apart"),
+    };
+
+    log::info!("TPS explained: {} {} counted over {} blocks", counted_transactions, counted, report.blocks_scanned);
+    match report.tps_average_method {
+        TpsAverageMethod::Simple => {
+            //A walk stops at the block before the window, which only marks where the window's time starts
+            let boundary = match report.window {
+                ScanWindow::ListedSlots(_) => "",
+                _ => ", a boundary block whose own transactions aren't counted,",
+            };
+            log::info!(
+                "TPS explained: block times run from {} in the oldest block{} to {} in the newest",
+                report.oldest_timestamp,
+                boundary,
+                report.newest_timestamp
+            );
+            log::info!(
+                "TPS explained: {} - {} = {} seconds",
+                report.newest_timestamp,
+                report.oldest_timestamp,
+                report.time_span_seconds
+            );
+            if report.time_span_seconds <= 0 {
+                log::info!(
+                    "TPS explained: a span of {} seconds can't be divided by, so TPS is 0",
+                    report.time_span_seconds
+                );
+            } else {
+                log::info!(
+                    "TPS explained: {} / {} = {}",
+                    counted_transactions,
+                    report.time_span_seconds,
+                    report.transactions_per_second
+                );
+            }
+        }
+        TpsAverageMethod::Weighted => {
+            let span_seconds = (report.counted_slots as f64) * NOMINAL_SLOT_SECONDS;
+            log::info!(
+                "TPS explained: the counted blocks span {} slots, at {} seconds each {:.1} seconds",
+                report.counted_slots,
+                NOMINAL_SLOT_SECONDS,
+                span_seconds
+            );
+            if report.counted_slots == 0 {
+                log::info!("TPS explained: a span of 0 slots can't be divided by, so TPS is 0");
+            } else {
+                log::info!(
+                    "TPS explained: {} / {:.1} = {}",
+                    counted_transactions,
+                    span_seconds,
+                    report.transactions_per_second
+                );
+            }
+        }
+    }
+}
+
+//Function that formats a ratio, or N/A when there is none
+fn format_ratio(ratio: Option<f64>) -> String {
+    match ratio {
+        Some(ratio) => format!("{:.2}", ratio),
+        None => "N/A".to_string(),
+    }
+}
+
+//Function that formats one amount of the reward totals in lamports, or says they weren't fetched
+fn format_rewards(rewards: Option<&RewardTotals>, amount: impl Fn(&RewardTotals) -> i64) -> String {
+    match rewards {
+        Some(rewards) => format!("{} lamports", amount(rewards)),
+        None => "not fetched".to_string(),
+    }
+}
+
+//Function that formats a block interval in milliseconds, or N/A when there is none
+fn format_interval(interval_ms: Option<u64>) -> String {
+    match interval_ms {
+        Some(interval_ms) => format!("{} ms", interval_ms),
+        None => "N/A".to_string(),
+    }
+}
+
+//Function that formats a slot time drift with the average slot time it stands for, or N/A when there is none
+fn format_drift(drift: Option<f64>) -> String {
+    match drift {
+        Some(drift) => {
+            let pace = if drift >= 0.0 { "slower" } else { "faster" };
+            format!(
+                "{:.0} ms per slot, {:.2}% {} than nominal",
+                (1.0 + drift) * NOMINAL_SLOT_SECONDS * 1000.0,
+                drift.abs() * 100.0,
+                pace
+            )
+        }
+        None => "N/A".to_string(),
+    }
+}
+
+//Function that formats a landed rate as a percentage, or N/A when there were no user transactions
+fn format_landed_rate(landed_rate: Option<f64>) -> String {
+    match landed_rate {
+        Some(landed_rate) => format!("{:.2}%", landed_rate * 100.0),
+        None => "N/A".to_string(),
+    }
+}
+
+//Function that prints the summary of a scan in the log line format
+pub fn print_text(report: &TpsReport) {
+    log::info!("Calculation took: {}", format_duration(report.calculation_duration));
+    match report.unit {
+        TpsUnit::Tps => {
+            log::info!("Total transactions per second over period: {}", report.transactions_per_second);
+        }
+        unit => {
+            log::info!(
+                "Total transactions per second over period: {} {}",
+                unit.scale(report.transactions_per_second),
+                unit.label()
+            );
+        }
+    }
+    log::info!("TPS average method: {}", report.tps_average_method.label());
+    log::info!(
+        "Compute units per second over period: {:.0} ({} compute units)",
+        report.compute_units_per_second,
+        report.compute_units
+    );
+    match report.transaction_details {
+        TransactionDetailLevel::Full => {}
+        TransactionDetailLevel::Signatures => {
+            log::warn!("Blocks were fetched with signatures only, TPS counts vote transactions too");
+        }
+        TransactionDetailLevel::None => {
+            log::warn!("Blocks were fetched without transactions, only block and slot figures are meaningful");
+        }
+    }
+    if let Some(weighted_transactions_per_second) = report.weighted_transactions_per_second {
+        log::info!(
+            "Weighted transactions per second (experimental, distinct fee payers per block): {} {}",
+            report.unit.scale(weighted_transactions_per_second),
+            report.unit.label()
+        );
+    }
+    if let Some(trimmed_transactions_per_second) = report.trimmed_transactions_per_second {
+        log::info!(
+            "Transactions per second without the boundary blocks: {} {} (untrimmed {} {})",
+            report.unit.scale(trimmed_transactions_per_second),
+            report.unit.label(),
+            report.unit.scale(report.transactions_per_second),
+            report.unit.label()
+        );
+    }
+    for (name, value) in &report.metrics {
+        log::info!("Metric {}: {}", name, value);
+    }
+    log::info!("Landed rate: {}", format_landed_rate(report.landed_rate));
+    log::info!("Vote to user ratio: {}", format_ratio(report.vote_to_user_ratio));
+    log::info!("System program transactions: {}", report.system_program_transactions);
+    log::info!("Durable nonce transactions: {}", report.durable_nonce_transactions);
+    log::info!(
+        "Multisig transactions: {} (average signatures: {})",
+        report.multisig_transactions,
+        format_ratio(report.average_signatures)
+    );
+    log::info!("Lookup table transactions: {}", report.lookup_table_transactions);
+    if let Some(threshold) = report.large_transfer_threshold {
+        log::info!(
+            "Transfers of at least {} SOL: {}",
+            lamports_to_sol(threshold),
+            report.large_transfer_count
+        );
+        for transfer in &report.large_transfers {
+            log::info!("Large transfer: {} SOL in {}", lamports_to_sol(transfer.lamports), transfer.signature);
+        }
+        if report.elided_large_transfers > 0 {
+            log::info!("Large transfers not listed: {}", report.elided_large_transfers);
+        }
+    }
+    log::info!(
+        "Instructions: {} ({:.2} per second)",
+        report.total_instructions,
+        report.instructions_per_second
+    );
+    if let Some(sizes) = &report.transaction_sizes {
+        log::info!(
+            "Transaction size: average {:.0} bytes (std dev {:.0}), p50 {}, p99 {}, max {}{}",
+            sizes.average,
+            sizes.std_dev,
+            sizes.p50,
+            sizes.p99,
+            sizes.max,
+            if sizes.approximate { ", percentiles estimated from a sample" } else { "" }
+        );
+    }
+    log::info!("Empty transactions: {}", report.empty_transactions);
+    if report.filtered_out > 0 {
+        log::info!("Transactions left out by the program filter: {}", report.filtered_out);
+    }
+    if let Some(fees) = &report.fee_distribution {
+        log::info!(
+            "Fee per transaction: median {} lamports, p90 {}, p99 {}, max {}",
+            fees.median,
+            fees.p90,
+            fees.p99,
+            fees.max
+        );
+    }
+    log::info!("Fees from successful transactions: {} lamports", report.fees_from_successful);
+    log::info!("Fees from failed transactions: {} lamports", report.fees_from_failed);
+    log::info!("Base fees: {} lamports, priority fees: {} lamports", report.base_fees, report.priority_fees);
+    log::info!("Estimated burned: {} SOL", lamports_to_sol(report.burned_fees));
+    match &report.rewards {
+        Some(rewards) => {
+            log::info!(
+                "Rewards: {} lamports (fee {}, rent {}, staking {}, voting {}, unknown {})",
+                rewards.total(),
+                rewards.fee,
+                rewards.rent,
+                rewards.staking,
+                rewards.voting,
+                rewards.unknown
+            );
+        }
+        None => log::info!("Rewards: not fetched"),
+    }
+    if report.warmup_blocks > 0 {
+        log::info!("Left out the {} newest blocks as warmup", report.warmup_blocks);
+    }
+    match report.tip_slot {
+        Some(tip_slot) if tip_slot != report.newest_slot => {
+            log::info!("Newest slot: {} ({} behind the tip {})", report.newest_slot, tip_slot - report.newest_slot, tip_slot);
+        }
+        _ => log::info!("Newest slot: {}", report.newest_slot),
+    }
+    log::info!("Oldest slot: {}", report.oldest_slot);
+    log::info!("Slot span: {} slots", report.slot_span);
+    log::info!("Time span: {} seconds", report.time_span_seconds);
+    log::info!("Slot time drift: {}", format_drift(report.slot_time_drift));
+    if let (Some(min), Some(median), Some(max)) = (
+        report.min_block_interval_ms,
+        report.median_block_interval_ms,
+        report.max_block_interval_ms,
+    ) {
+        log::info!("Block interval: median {} ms, min {} ms, max {} ms", median, min, max);
+    }
+
+    if report.window_truncated {
+        match report.window {
+            ScanWindow::Seconds(seconds) => {
+                log::warn!(
+                    "Window truncated: the result covers {} of the requested {} seconds",
+                    report.window_covered_seconds,
+                    seconds
+                );
+            }
+            _ => {
+                log::warn!(
+                    "Window truncated: the result covers {} blocks over {} seconds",
+                    report.blocks_scanned,
+                    report.window_covered_seconds
+                );
+            }
+        }
+    }
+
+    if report.truncated_by_pruning {
+        log::warn!("Window was truncated by ledger pruning on the endpoint");
+    }
+
+    if report.truncated_by_cleanup {
+        log::warn!("Window was truncated at a block the endpoint has cleaned up from its ledger");
+    }
+
+    if report.time_limited {
+        log::warn!("Scan was stopped by the maximum runtime, the result covers part of the window");
+    }
+
+    if let Some(official) = &report.official {
+        log::info!(
+            "Node-reported TPS over its last {} samples ({} seconds): {} {} including votes",
+            official.samples,
+            official.sample_seconds,
+            report.unit.scale(official.transactions_per_second),
+            report.unit.label()
+        );
+        match official.non_vote_transactions_per_second {
+            Some(non_vote_transactions_per_second) => {
+                log::info!(
+                    "Node-reported non-vote TPS: {} {}, the figure that compares with the user TPS above",
+                    report.unit.scale(non_vote_transactions_per_second),
+                    report.unit.label()
+                );
+            }
+            None => {
+                log::warn!("The node doesn't report non-vote TPS, its figure counts votes while ours doesn't");
+            }
+        }
+    }
+
+    if report.reward_only_blocks > 0 {
+        log::info!("Reward-only blocks, without transactions: {}", report.reward_only_blocks);
+    }
+
+    if report.parallel_counting {
+        log::info!("Blocks were counted in parallel on {} threads", report.counting_threads);
+    }
+
+    for (program_id, count) in top_counts(&report.instruction_counts, TOP_PROGRAMS) {
+        log::info!("Program {} instructions: {}", program_id, count);
+    }
+
+    for (program_id, count) in top_counts(&report.listed_program_counts, report.listed_program_counts.len()) {
+        log::info!("Listed program {} user transactions: {}", program_id, count);
+    }
+
+    for program in &report.top_programs_by_tps {
+        log::info!(
+            "Program {} user transactions per second: {} {} ({} txns)",
+            program.program_id,
+            report.unit.scale(program.transactions_per_second),
+            report.unit.label(),
+            program.user_transactions
+        );
+    }
+
+    for signer in &report.top_signers {
+        log::info!("Signer {} user transactions: {}", signer.fee_payer, signer.user_transactions);
+    }
+    if report.top_signers_approximate {
+        log::warn!("Too many fee payers to track them all, signer counts are lower bounds");
+    }
+
+    for (label, count) in &report.counted_instruction_counts {
+        log::info!("Instruction {}: {} calls", label, count);
+    }
+    if let Some(discriminator_program) = &report.discriminator_program {
+        for (discriminator, count) in top_counts(&report.discriminator_counts, TOP_PROGRAMS) {
+            log::info!("Program {} discriminator {}: {} txns", discriminator_program, discriminator, count);
+        }
+    }
+}
+
+//Function that writes the report as JSON to a file. The file only changes once the new report is complete,
+//so one that is rewritten while a scan goes always holds a whole report
+pub fn write_json_report(path: &Path, report: &TpsReport) -> Result<(), TpsError> {
+    let partial_path = path.with_extension("partial");
+    fs::write(&partial_path, render_json(report, false))?;
+    fs::rename(&partial_path, path)?;
+
+    Ok(())
+}
+
+//Function that renders the report as CSV, ready to plot: one bucket row per bucket when the window was split
+//into buckets, oldest first, then a summary row over the whole window. The first column tells them apart
+pub fn render_csv(report: &TpsReport) -> String {
+    let mut csv = String::from("row,bucket_start,bucket_end,tps,user_txns,blocks\n");
+
+    for bucket in &report.buckets {
+        csv.push_str(
+            &format!(
+                "bucket,{},{},{},{},{}\n",
+                bucket.start_timestamp,
+                bucket.end_timestamp,
+                bucket.transactions_per_second,
+                bucket.user_transactions,
+                bucket.blocks
+            )
+        );
+    }
+
+    csv.push_str(
+        &format!(
+            "summary,{},{},{},{},{}\n",
+            report.oldest_timestamp,
+            report.newest_timestamp,
+            report.transactions_per_second,
+            report.user_transactions,
+            report.blocks_scanned
+        )
+    );
+
+    csv
+}
+
+//Function that writes the report as CSV to a file, the same way as the JSON report
+pub fn write_csv_report(path: &Path, report: &TpsReport) -> Result<(), TpsError> {
+    let partial_path = path.with_extension("partial");
+    fs::write(&partial_path, render_csv(report))?;
+    fs::rename(&partial_path, path)?;
+
+    Ok(())
+}
+
+//Function that renders the JSON Schema of the report, as printed by --print-schema
+pub fn render_json_schema() -> String {
+    serde_json::to_string_pretty(&schemars::schema_for!(TpsReport)).expect("schema serializes to JSON")
+}
+
+//Short names a template can use for the report fields people ask for most. An alias never takes the name of a
+//report field, which would then be out of reach
+const TEMPLATE_ALIASES: &[(&str, &str)] = &[
+    ("tps", "transactions_per_second"),
+    ("user_tx", "user_transactions"),
+    ("span", "time_span_seconds"),
+];
+
+//Piece of a parsed output template
+enum TemplatePart<'a> {
+    Text(&'a str),
+    Field(&'a str),
+}
+
+//Function that splits an output template into literal text and {field} placeholders, resolving aliases
+//to the report fields they stand for and rejecting any other name the report doesn't have.
+//{{ and }} stand for literal braces
+fn parse_template(template: &str) -> Result<Vec<TemplatePart<'_>>, TpsError> {
+    let schema = schemars::schema_for!(TpsReport);
+    let fields = schema.schema.object.as_ref().map(|object| &object.properties);
+
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(index) = rest.find(['{', '}']) {
+        parts.push(TemplatePart::Text(&rest[..index]));
+
+        if rest[index..].starts_with("{{") || rest[index..].starts_with("}}") {
+            parts.push(TemplatePart::Text(&rest[index..index + 1]));
+            rest = &rest[index + 2..];
+            continue;
+        }
+        if rest[index..].starts_with('}') {
+            return Err(TpsError::InvalidTemplate("unmatched '}', write '}}' for a literal brace".to_string()));
+        }
+
+        let end = rest[index..]
+            .find('}')
+            .ok_or_else(|| TpsError::InvalidTemplate("unclosed '{', write '{{' for a literal brace".to_string()))?;
+        let name = rest[index + 1..index + end].trim();
+        let field = TEMPLATE_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, field)| *field);
+
+        if !fields.is_some_and(|fields| fields.contains_key(field)) {
+            return Err(TpsError::InvalidTemplate(format!("the report has no field '{}'", name)));
+        }
+
+        parts.push(TemplatePart::Field(field));
+        rest = &rest[index + end + 1..];
+    }
+    parts.push(TemplatePart::Text(rest));
+
+    Ok(parts)
+}
+
+//Function that checks an output template before a scan, so a typo doesn't cost a whole scan to find out
+pub fn check_template(template: &str) -> Result<(), TpsError> {
+    parse_template(template).map(|_| ())
+}
+
+//Function that renders the summary of a scan through an output template, replacing every {field} with
+//the value of the report field of that name as it appears in the JSON report. Missing values print as N/A,
+//nested ones as compact JSON
+pub fn render_template(template: &str, report: &TpsReport) -> Result<String, TpsError> {
+    let values = serde_json::to_value(report).expect("report serializes to JSON");
+    let mut rendered = String::new();
+
+    for part in parse_template(template)? {
+        match part {
+            TemplatePart::Text(text) => rendered.push_str(text),
+            TemplatePart::Field(field) => {
+                match &values[field] {
+                    serde_json::Value::Null => rendered.push_str("N/A"),
+                    serde_json::Value::String(value) => rendered.push_str(value),
+                    value => rendered.push_str(&value.to_string()),
+                }
+            }
+        }
+    }
+
+    Ok(rendered)
+}
+
+//Function that renders the summary of a scan as JSON, pretty-printed unless compact is set.
+//Both forms hold exactly the same values
+pub fn render_json(report: &TpsReport, compact: bool) -> String {
+    let json = if compact {
+        serde_json::to_string(report)
+    } else {
+        serde_json::to_string_pretty(report)
+    };
+
+    json.expect("report serializes to JSON")
+}
+
+//Function that renders the outcome of summarizing one file of a batch as a single JSON line,
+//holding either its report or the reason it failed
+pub fn render_batch_line(input: &Path, result: &Result<TpsReport, TpsError>) -> String {
+    #[derive(Serialize)]
+    struct BatchLine<'a> {
+        input: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        report: Option<&'a TpsReport>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    }
+
+    let line = BatchLine {
+        input: input.display().to_string(),
+        report: result.as_ref().ok(),
+        error: result.as_ref().err().map(ToString::to_string),
+    };
+
+    serde_json::to_string(&line).expect("batch line serializes to JSON")
+}
+
+//Function that renders the summaries of scans over several windows as one JSON array, in the order of the windows
+pub fn render_json_windows(reports: &[TpsReport], compact: bool) -> String {
+    let json = if compact {
+        serde_json::to_string(reports)
+    } else {
+        serde_json::to_string_pretty(reports)
+    };
+
+    json.expect("reports serialize to JSON")
+}
+
+//Function that prints the summary of a scan as an aligned key/value table
+pub fn print_table(report: &TpsReport) {
+    let mut rows: Vec<(String, String)> = [
+        ("Window", report.window.to_string()),
+        (
+            "TPS (user)",
+            format!("{:.2} {}", report.unit.scale(report.transactions_per_second), report.unit.label()),
+        ),
+        ("TPS average method", report.tps_average_method.label().to_string()),
+        ("Compute units per second", format!("{:.0}", report.compute_units_per_second)),
+        ("Compute units", report.compute_units.to_string()),
+        ("Landed rate", format_landed_rate(report.landed_rate)),
+        ("Vote to user ratio", format_ratio(report.vote_to_user_ratio)),
+        ("User transactions", report.user_transactions.to_string()),
+        ("Vote transactions", report.vote_transactions.to_string()),
+        ("Empty transactions", report.empty_transactions.to_string()),
+        ("Filtered out transactions", report.filtered_out.to_string()),
+        ("Total transactions", report.total_transactions.to_string()),
+        ("System program transactions", report.system_program_transactions.to_string()),
+        ("Durable nonce transactions", report.durable_nonce_transactions.to_string()),
+        ("Multisig transactions", report.multisig_transactions.to_string()),
+        ("Average signatures", format_ratio(report.average_signatures)),
+        ("Lookup table transactions", report.lookup_table_transactions.to_string()),
+        ("Instructions", report.total_instructions.to_string()),
+        ("Instructions per second", format!("{:.2}", report.instructions_per_second)),
+        ("Fees", format!("{} lamports", report.total_fees)),
+        ("Fees from successful", format!("{} lamports", report.fees_from_successful)),
+        ("Fees from failed", format!("{} lamports", report.fees_from_failed)),
+        ("Base fees", format!("{} lamports", report.base_fees)),
+        ("Priority fees", format!("{} lamports", report.priority_fees)),
+        ("Estimated burned", format!("{} SOL", lamports_to_sol(report.burned_fees))),
+        ("Rewards", format_rewards(report.rewards.as_ref(), RewardTotals::total)),
+        ("Fee rewards", format_rewards(report.rewards.as_ref(), |rewards| rewards.fee)),
+        ("Rent rewards", format_rewards(report.rewards.as_ref(), |rewards| rewards.rent)),
+        ("Staking rewards", format_rewards(report.rewards.as_ref(), |rewards| rewards.staking)),
+        ("Voting rewards", format_rewards(report.rewards.as_ref(), |rewards| rewards.voting)),
+        ("Blocks scanned", report.blocks_scanned.to_string()),
+        ("Skipped slots", report.skipped_slots.to_string()),
+        ("Skip rate", format!("{:.2}%", report.skip_rate * 100.0)),
+        ("Reward-only blocks", report.reward_only_blocks.to_string()),
+        ("Newest slot", report.newest_slot.to_string()),
+        ("Oldest slot", report.oldest_slot.to_string()),
+        ("Slot span", format!("{} slots", report.slot_span)),
+        ("Time span", format!("{} s", report.time_span_seconds)),
+        ("Slot time drift", format_drift(report.slot_time_drift)),
+        ("Median block interval", format_interval(report.median_block_interval_ms)),
+        ("Min block interval", format_interval(report.min_block_interval_ms)),
+        ("Max block interval", format_interval(report.max_block_interval_ms)),
+        ("Window covered", format!("{} s", report.window_covered_seconds)),
+        ("Window truncated", if report.window_truncated { "yes" } else { "no" }.to_string()),
+        ("Truncated by pruning", if report.truncated_by_pruning { "yes" } else { "no" }.to_string()),
+        ("Truncated by cleanup", if report.truncated_by_cleanup { "yes" } else { "no" }.to_string()),
+        ("Time limited", if report.time_limited { "yes" } else { "no" }.to_string()),
+        ("Counting threads", report.counting_threads.to_string()),
+        ("Calculation time", format_duration(report.calculation_duration)),
+    ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+    if let Some(weighted_transactions_per_second) = report.weighted_transactions_per_second {
+        rows.push((
+            "Weighted TPS (experimental)".to_string(),
+            format!("{:.2} {}", report.unit.scale(weighted_transactions_per_second), report.unit.label()),
+        ));
+    }
+
+    if let Some(trimmed_transactions_per_second) = report.trimmed_transactions_per_second {
+        rows.push((
+            "TPS (boundaries trimmed)".to_string(),
+            format!("{:.2} {}", report.unit.scale(trimmed_transactions_per_second), report.unit.label()),
+        ));
+    }
+
+    for (name, value) in &report.metrics {
+        rows.push((format!("Metric {}", name), value.to_string()));
+    }
+
+    if let Some(threshold) = report.large_transfer_threshold {
+        rows.push((
+            format!("Transfers of at least {} SOL", lamports_to_sol(threshold)),
+            report.large_transfer_count.to_string(),
+        ));
+        for (rank, transfer) in report.large_transfers.iter().enumerate() {
+            rows.push((
+                format!("Large transfer #{}", rank + 1),
+                format!("{} SOL in {}", lamports_to_sol(transfer.lamports), transfer.signature),
+            ));
+        }
+        if report.elided_large_transfers > 0 {
+            rows.push(("Large transfers not listed".to_string(), report.elided_large_transfers.to_string()));
+        }
+    }
+
+    for (rank, (program_id, count)) in top_counts(&report.instruction_counts, TOP_PROGRAMS)
+        .into_iter()
+        .enumerate() {
+        rows.push((format!("Top program #{}", rank + 1), format!("{} ({} ix)", program_id, count)));
+    }
+
+    if let Some(sizes) = &report.transaction_sizes {
+        rows.push(("Transaction size (average)".to_string(), format!("{:.0} bytes", sizes.average)));
+        rows.push(("Transaction size (std dev)".to_string(), format!("{:.0} bytes", sizes.std_dev)));
+        let estimate = if sizes.approximate { ", estimated" } else { "" };
+        rows.push(("Transaction size (p50)".to_string(), format!("{} bytes{}", sizes.p50, estimate)));
+        rows.push(("Transaction size (p99)".to_string(), format!("{} bytes{}", sizes.p99, estimate)));
+        rows.push(("Transaction size (max)".to_string(), format!("{} bytes", sizes.max)));
+    }
+
+    if let Some(fees) = &report.fee_distribution {
+        rows.push(("Fee (median)".to_string(), format!("{} lamports", fees.median)));
+        rows.push(("Fee (p90)".to_string(), format!("{} lamports", fees.p90)));
+        rows.push(("Fee (p99)".to_string(), format!("{} lamports", fees.p99)));
+        rows.push(("Fee (max)".to_string(), format!("{} lamports", fees.max)));
+    }
+
+    if let Some(official) = &report.official {
+        rows.push((
+            "Node TPS (all)".to_string(),
+            format!("{:.2} {}", report.unit.scale(official.transactions_per_second), report.unit.label()),
+        ));
+        if let Some(non_vote_transactions_per_second) = official.non_vote_transactions_per_second {
+            rows.push((
+                "Node TPS (non-vote)".to_string(),
+                format!("{:.2} {}", report.unit.scale(non_vote_transactions_per_second), report.unit.label()),
+            ));
+        }
+    }
+
+    for (program_id, count) in top_counts(&report.listed_program_counts, report.listed_program_counts.len()) {
+        rows.push((format!("Listed program {}", program_id), format!("{} txns", count)));
+    }
+
+    for (rank, program) in report.top_programs_by_tps.iter().enumerate() {
+        rows.push((
+            format!("Top program by TPS #{}", rank + 1),
+            format!(
+                "{} ({:.2} {})",
+                program.program_id,
+                report.unit.scale(program.transactions_per_second),
+                report.unit.label()
+            ),
+        ));
+    }
+
+    for (rank, signer) in report.top_signers.iter().enumerate() {
+        rows.push((
+            format!("Top signer #{}", rank + 1),
+            format!("{} ({} txns)", signer.fee_payer, signer.user_transactions),
+        ));
+    }
+
+    for (discriminator, count) in top_counts(&report.discriminator_counts, TOP_PROGRAMS) {
+        rows.push((format!("Discriminator {}", discriminator), format!("{} txns", count)));
+    }
+
+    for (label, count) in &report.counted_instruction_counts {
+        rows.push((format!("Instruction {}", label), format!("{} calls", count)));
+    }
+
+    print!("{}", render_table_rows(&rows));
+}
+
+//Lays the rows out as a two-column table. Widths are counted in chars, as the padding is, so values with
+//non-ASCII text such as µs line up
+fn render_table_rows(rows: &[(String, String)]) -> String {
+    let key_width = rows
+        .iter()
+        .map(|(key, _)| key.chars().count())
+        .max()
+        .unwrap_or(0);
+    let value_width = rows
+        .iter()
+        .map(|(_, value)| value.chars().count())
+        .max()
+        .unwrap_or(0);
+    let separator = format!("+-{}-+-{}-+", "-".repeat(key_width), "-".repeat(value_width));
+
+    let mut table = format!("{}\n", separator);
+    for (key, value) in rows {
+        table.push_str(&format!("| {:<key_width$} | {:>value_width$} |\n", key, value));
+    }
+    table.push_str(&format!("{}\n", separator));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::{ calculate_for_range, ScanOptions, ScanWindow };
+    use crate::testutil::{ chain, source };
+
+    //Function that scans a few built blocks, with the given options, for a report to render
+    fn scanned_report(options: &ScanOptions) -> TpsReport {
+        calculate_for_range(&source(chain(6, 1_000)), ScanWindow::Seconds(3), options).unwrap()
+    }
+
+    #[test]
+    fn json_report_carries_the_schema_version() {
+        let report = scanned_report(&ScanOptions::default());
+        let json: serde_json::Value = serde_json::from_str(&render_json(&report, true)).unwrap();
+
+        assert_eq!(REPORT_SCHEMA_VERSION, 2);
+        assert_eq!(report.schema_version, REPORT_SCHEMA_VERSION);
+        assert_eq!(json["schema_version"], 2);
+    }
+
+    #[test]
+    fn rewards_are_null_for_blocks_fetched_without_them() {
+        let report = scanned_report(&ScanOptions {
+            skip_rewards: true,
+            ..ScanOptions::default()
+        });
+        let json: serde_json::Value = serde_json::from_str(&render_json(&report, true)).unwrap();
+
+        assert!(json["rewards"].is_null());
+        assert!(render_json_schema().contains("\"schema_version\""));
+    }
+
+    #[test]
+    fn template_aliases_leave_the_report_fields_reachable() {
+        let report = scanned_report(&ScanOptions::default());
+        let window = serde_json::to_value(report.window).unwrap().to_string();
+
+        assert_eq!(render_template("{span}s", &report).unwrap(), "3s");
+        assert_eq!(render_template("{window}", &report).unwrap(), window);
+
+        let schema = schemars::schema_for!(TpsReport);
+        let fields = &schema.schema.object.as_ref().unwrap().properties;
+        for (alias, field) in TEMPLATE_ALIASES {
+            assert!(!fields.contains_key(*alias), "alias {} is a report field", alias);
+            assert!(fields.contains_key(*field), "alias {} stands for a missing field", alias);
+        }
+    }
+
+    #[test]
+    fn table_rows_line_up_with_non_ascii_values() {
+        let rows = vec![
+            ("Scan duration".to_string(), "850µs".to_string()),
+            ("Blocks".to_string(), "12".to_string()),
+        ];
+        let table = render_table_rows(&rows);
+        let widths: Vec<usize> = table.lines().map(|line| line.chars().count()).collect();
+
+        assert_eq!(widths.len(), 4);
+        assert!(widths.iter().all(|width| *width == widths[0]), "{}", table);
+        assert!(table.contains("| Scan duration | 850µs |"));
+    }
+}
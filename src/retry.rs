@@ -0,0 +1,89 @@
+use std::thread;
+use std::time::Duration;
+
+use solana_transaction_status::EncodedConfirmedBlock;
+
+use crate::error::{ is_transient, TpsError };
+use crate::source::{ BlockSource, TransactionDetailLevel };
+
+//Pause before the first retry of a failed request. Every further retry waits twice as long as the one before
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+//Block source that retries requests which failed for a reason that may pass, like a timeout, a dropped connection
+//or an overloaded endpoint. Errors that will never succeed, like a slot before the first available block or
+//invalid parameters, are returned straight away so they don't use up the retries
+pub struct RetryingBlockSource<S> {
+    inner: S,
+    max_retries: u32,
+}
+
+impl<S: BlockSource> RetryingBlockSource<S> {
+    pub fn new(inner: S, max_retries: u32) -> Self {
+        RetryingBlockSource { inner, max_retries }
+    }
+
+    //Function that runs the request, retrying it with exponential backoff while it fails with a transient error
+    fn with_retries<T>(&self, description: &str, request: impl Fn(&S) -> Result<T, TpsError>) -> Result<T, TpsError> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut retries_left = self.max_retries;
+
+        loop {
+            match request(&self.inner) {
+                Err(TpsError::Rpc(err)) if is_transient(&err) && retries_left > 0 => {
+                    retries_left -= 1;
+                    log::warn!(
+                        "Request for {} failed with a transient error, retrying in {:?} ({} retries left): {}",
+                        description,
+                        backoff,
+                        retries_left,
+                        err
+                    );
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                Err(TpsError::Rpc(err)) if !is_transient(&err) => {
+                    log::debug!("Request for {} failed with a permanent error, not retrying", description);
+                    return Err(TpsError::Rpc(err));
+                }
+                result => {
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+impl<S: BlockSource> BlockSource for RetryingBlockSource<S> {
+    fn get_slot(&self) -> Result<u64, TpsError> {
+        self.with_retries("the tip slot", |inner| inner.get_slot())
+    }
+
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+        self.with_retries(&format!("block {}", slot), |inner| inner.get_block(slot))
+    }
+
+    fn get_block_with_details(
+        &self,
+        slot: u64,
+        details: TransactionDetailLevel,
+        rewards: bool
+    ) -> Result<(EncodedConfirmedBlock, Option<u64>), TpsError> {
+        self.with_retries(&format!("block {}", slot), |inner| {
+            inner.get_block_with_details(slot, details, rewards)
+        })
+    }
+
+    fn get_block_time(&self, slot: u64) -> Result<Option<i64>, TpsError> {
+        self.with_retries(&format!("the block time of slot {}", slot), |inner| inner.get_block_time(slot))
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        self.with_retries(&format!("the blocks in slots {} to {}", start_slot, end_slot), |inner| {
+            inner.get_blocks(start_slot, end_slot)
+        })
+    }
+
+    fn get_first_available_block(&self) -> Result<u64, TpsError> {
+        self.with_retries("the first available block", |inner| inner.get_first_available_block())
+    }
+}
@@ -0,0 +1,1830 @@
+use std::cell::{ Cell, RefCell };
+use std::collections::{ BTreeMap, BTreeSet, HashMap, HashSet };
+use std::fmt;
+use std::io::BufRead;
+use std::mem;
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant };
+
+use chrono::{ DateTime, Utc };
+use clap::ValueEnum;
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use solana_client::{
+    client_error::ClientErrorKind,
+    rpc_client::RpcClient,
+    rpc_custom_error,
+    rpc_request::RpcError,
+};
+use solana_transaction_status::EncodedConfirmedBlock;
+
+use crate::analysis::{
+    analyze_block,
+    block_intervals,
+    bucket_tps,
+    burned_fees,
+    calculate_tps,
+    landed_rate,
+    slot_time_drift,
+    tps_from_span,
+    trimmed_tps,
+    vote_to_user_ratio,
+    AnalysisOptions,
+    BlockAnalysis,
+    FeeDistribution,
+    SignerCounts,
+    SizeStats,
+    TpsAverageMethod,
+    MIN_TRIMMED_BLOCKS,
+    STREAMING_SIZE_SAMPLE,
+};
+use crate::clock::{ Clock, SystemClock };
+use crate::dump::{ BlockDump, SlotDump };
+use crate::metrics::{ Metric, MetricKind, MetricWindow };
+use crate::error::{ rpc_error_code, set_current_slot, TpsError };
+use crate::records::FileBlockSource;
+use crate::report::{
+    rank_programs_by_tps,
+    rank_signers,
+    write_json_report,
+    OfficialTps,
+    TpsReport,
+    TpsUnit,
+    REPORT_SCHEMA_VERSION,
+    TOP_PROGRAMS,
+};
+use crate::source::{ block_timestamp, clone_block, BlockSource, PerformanceSamples, TransactionDetailLevel };
+use crate::stop::{ stop_conditions, WalkState };
+
+//How far back from the newest block a scan goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanWindow {
+    //Every block produced in the last given number of seconds
+    Seconds(i64),
+    //The given number of most recently produced blocks
+    LastBlocks(u64),
+    //Every block from the given slot up to the newest block
+    SinceSlot(u64),
+    //The blocks in a given list of this many slots, wherever they are
+    ListedSlots(u64),
+}
+
+impl fmt::Display for ScanWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanWindow::Seconds(seconds) => write!(f, "{} s", seconds),
+            ScanWindow::LastBlocks(blocks) => write!(f, "last {} blocks", blocks),
+            ScanWindow::SinceSlot(slot) => write!(f, "since slot {}", slot),
+            ScanWindow::ListedSlots(slots) => write!(f, "{} listed slots", slots),
+        }
+    }
+}
+
+//What a scan does when the endpoint's history ends before the window does
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InsufficientHistory {
+    //Fail the scan
+    Error,
+    //Report the part of the window that is available
+    Truncate,
+    //Report the part of the window that is available, and log a warning about it
+    #[default]
+    Warn,
+}
+
+//Settings that change what a scan counts, independently of the window it covers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanOptions {
+    //How each block is analyzed
+    pub analysis: AnalysisOptions,
+    //Also report the experimental weighted TPS, counting distinct fee payers per block instead of transactions
+    pub weighted: bool,
+    //Also report TPS without the newest and oldest counted block, which only partly overlap the window
+    pub trim_boundaries: bool,
+    //Start the backward walk from this slot instead of the chain tip, so a past window can be reproduced
+    pub seed_slot: Option<u64>,
+    //Write every scanned slot and its block time to this CSV file as the scan goes
+    pub dump_slots: Option<PathBuf>,
+    //Write every fetched block to this NDJSON file as the scan goes, so the scan can be replayed
+    pub dump_blocks: Option<PathBuf>,
+    //What to do when the window reaches further back than the endpoint's history
+    pub on_insufficient_history: InsufficientHistory,
+    //How much of each transaction blocks are fetched with. Anything that inspects instructions needs full detail
+    pub transaction_details: TransactionDetailLevel,
+    //Fetch blocks without their rewards, for smaller responses. The report then has no reward totals
+    pub skip_rewards: bool,
+    //Metrics worked out over the counted blocks on top of the summary, reported by name
+    pub metrics: Vec<MetricKind>,
+    //Leave out this many blocks at the start of the walk, from both the transactions counted and the time span
+    pub warmup_blocks: u64,
+    //Start the walk at least this many slots behind the tip, where blocks have settled. Ignored with a seed slot
+    pub tip_lag: u64,
+    //How the window's transactions are averaged into TPS
+    pub tps_average_method: TpsAverageMethod,
+    //Analyze the fetched blocks in parallel once the walk is done, instead of one by one while walking.
+    //Fetching stays sequential, and the blocks are held in memory until then
+    pub parallel_counting: bool,
+    //Keep only a sample of the transaction sizes, so memory stays bounded however wide the window. The size
+    //percentiles are then estimates. Blocks are always summed as they are counted
+    pub streaming: bool,
+    //Also report this many fee payers that paid for the most user transactions
+    pub top_signers: Option<usize>,
+    //Stop early once this many blocks have been counted, reporting the part of the window scanned so far
+    pub max_blocks: Option<u64>,
+    //Stop early once the scan has run this long, reporting the part of the window scanned so far
+    pub max_runtime: Option<Duration>,
+    //Also report TPS over consecutive buckets of this many seconds, to show how throughput moved within the window
+    pub bucket_seconds: Option<u64>,
+    //Keep writing the report so far to this JSON file, marked as partial, every flush interval while walking.
+    //A scan that dies leaves its last flush behind
+    pub flush_output: Option<PathBuf>,
+    pub flush_interval: Option<Duration>,
+}
+
+//A fetched block along with its number of transactions, if the detail level it was fetched with tells
+type FetchedBlock = (EncodedConfirmedBlock, Option<u64>);
+
+//Rough cost of scanning a window, used to set expectations before running a scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanEstimate {
+    pub estimated_blocks: u64,
+    pub estimated_calls: u64,
+    pub estimated_seconds: f64,
+}
+
+//Function thhat retrieves block object based on a given block number
+pub fn get_block(client: &impl BlockSource, block_num: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+    let _span = tracing::trace_span!("get_block", block_num).entered();
+    log::debug!("Getting block number: {}", block_num);
+
+    client.get_block(block_num)
+}
+
+//Function that retrieves the block time of the block in the given slot, without fetching the whole block where the
+//source allows it. None if the block has no block time
+pub fn get_block_time(client: &impl BlockSource, block_num: u64) -> Result<Option<i64>, TpsError> {
+    let _span = tracing::trace_span!("get_block_time", block_num).entered();
+    log::debug!("Getting block time of block number: {}", block_num);
+
+    client.get_block_time(block_num)
+}
+
+//Function that retrieves a block with the given transaction detail, and its rewards if asked for, along with its
+//number of transactions if known
+fn get_block_with_details(
+    client: &impl BlockSource,
+    block_num: u64,
+    details: TransactionDetailLevel,
+    rewards: bool
+) -> Result<(EncodedConfirmedBlock, Option<u64>), TpsError> {
+    let _span = tracing::trace_span!("get_block", block_num).entered();
+    log::debug!("Getting block number: {}", block_num);
+    set_current_slot(Some(block_num));
+
+    //Endpoints sometimes leave the transactions out of a block they were asked to include them in. Counting
+    //such a block as empty would silently under-report, so it is fetched once more and otherwise fails the scan
+    let (block, transaction_count) = client.get_block_with_details(block_num, details, rewards)?;
+    if transaction_count.is_some() || details == TransactionDetailLevel::None {
+        return Ok((block, transaction_count));
+    }
+
+    log::warn!("Block in slot {} came back without its transactions, fetching it again", block_num);
+    match client.get_block_with_details(block_num, details, rewards)? {
+        (_, None) => Err(TpsError::DetailStripped(block_num)),
+        refetched => Ok(refetched),
+    }
+}
+
+//How many slots below the lagged tip are tried before giving up on finding a produced block to start from
+const MAX_TIP_SEARCH_SLOTS: u64 = 32;
+
+//Function that fetches the block to start the walk from: the first produced block at least the given number of
+//slots behind the tip. Right at the tip the newest slot may not have a block yet, or may still be skipped,
+//in which case the produced slots just below it are listed in one request instead of tried one by one
+fn settled_block_behind_tip(
+    client: &impl BlockSource,
+    tip_slot: u64,
+    tip_lag: u64,
+    details: TransactionDetailLevel,
+    rewards: bool
+) -> Result<(u64, FetchedBlock), TpsError> {
+    let lagged_slot = tip_slot.saturating_sub(tip_lag);
+
+    match get_block_with_details(client, lagged_slot, details, rewards) {
+        Ok(block) => Ok((lagged_slot, block)),
+        Err(TpsError::NoBlockAvailable(_)) if lagged_slot > 0 => {
+            let oldest_tried_slot = lagged_slot.saturating_sub(MAX_TIP_SEARCH_SLOTS);
+            log::debug!("No block in slot {} near the tip, listing the blocks from slot {}", lagged_slot, oldest_tried_slot);
+
+            let newest_produced_slot = client
+                .get_blocks(oldest_tried_slot, lagged_slot - 1)?
+                .last()
+                .copied()
+                .ok_or(TpsError::NoBlockAvailable(lagged_slot))?;
+
+            Ok((newest_produced_slot, get_block_with_details(client, newest_produced_slot, details, rewards)?))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+//JSON-RPC error code of a request for a method the endpoint doesn't serve
+const JSON_RPC_METHOD_NOT_FOUND: i64 = -32601;
+
+//Most slots a single getBlocks request may cover
+const MAX_GET_BLOCKS_RANGE: u64 = 500_000;
+
+//Function that lists the slots between start and end, both included, that produced a block, in as few
+//getBlocks requests as the endpoint's range limit allows. None when the endpoint doesn't serve getBlocks
+fn listed_slots(client: &impl BlockSource, start_slot: u64, end_slot: u64) -> Result<Option<Vec<u64>>, TpsError> {
+    let mut slots = Vec::new();
+    let mut chunk_start = start_slot;
+
+    while chunk_start <= end_slot {
+        let chunk_end = end_slot.min(chunk_start.saturating_add(MAX_GET_BLOCKS_RANGE - 1));
+        match client.get_blocks(chunk_start, chunk_end) {
+            Ok(chunk) => slots.extend(chunk),
+            Err(TpsError::Rpc(err)) if rpc_error_code(&err) == Some(JSON_RPC_METHOD_NOT_FOUND) => {
+                log::info!("The endpoint doesn't serve getBlocks, following parent slots instead");
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(err);
+            }
+        }
+        chunk_start = chunk_end + 1;
+    }
+
+    Ok(Some(slots))
+}
+
+//Function that returns the newest produced slot at or below the given one, but not below the oldest slot, along
+//with its block time. None when none of the slots just below it produced a block
+fn produced_block_time_at_or_below(
+    client: &impl BlockSource,
+    slot: u64,
+    oldest_slot: u64
+) -> Result<Option<(u64, i64)>, TpsError> {
+    let start_slot = slot.saturating_sub(MAX_TIP_SEARCH_SLOTS).max(oldest_slot);
+    let Some(produced_slot) = client.get_blocks(start_slot, slot)?.last().copied() else {
+        return Ok(None);
+    };
+    let block_time = get_block_time(client, produced_slot)?.ok_or(TpsError::MissingBlockTime(produced_slot))?;
+
+    Ok(Some((produced_slot, block_time)))
+}
+
+//Function that finds the slot a time window starts at: a produced slot whose block time is at or before the start
+//time, close below the first one after it. Block times only grow with the slot, so the slots are searched by
+//block time, stepping back further and further from the newest slot and then halving the range in between.
+//Only block times are fetched, and the oldest slot is returned when the history ends inside the window
+fn window_start_slot(
+    client: &impl BlockSource,
+    newest_slot: u64,
+    start_time: i64,
+    oldest_slot: u64
+) -> Result<u64, TpsError> {
+    let mut newer_slot = newest_slot;
+    let mut step = MAX_TIP_SEARCH_SLOTS;
+    let mut start_slot = loop {
+        let probed_slot = newest_slot.saturating_sub(step).max(oldest_slot);
+        match produced_block_time_at_or_below(client, probed_slot, oldest_slot)? {
+            Some((produced_slot, block_time)) if block_time <= start_time => break produced_slot,
+            _ => newer_slot = probed_slot,
+        }
+        if probed_slot == oldest_slot {
+            return Ok(oldest_slot);
+        }
+        step = step.saturating_mul(2);
+    };
+
+    //The slots between a probed slot and the produced one found below it are all skipped, so the search goes on
+    //above the probed slot. Every slot of the last few is looked at anyway when their blocks are listed
+    let mut older_slot = start_slot;
+    while newer_slot.saturating_sub(older_slot) > MAX_TIP_SEARCH_SLOTS {
+        let middle_slot = older_slot + (newer_slot - older_slot) / 2;
+        match produced_block_time_at_or_below(client, middle_slot, older_slot)? {
+            Some((produced_slot, block_time)) if block_time <= start_time => {
+                start_slot = produced_slot;
+                older_slot = middle_slot;
+            }
+            _ => newer_slot = middle_slot,
+        }
+    }
+
+    Ok(start_slot)
+}
+
+//Function that lists the slots a time window before the newest block produced, from a slot at or before its start
+//on. None when the endpoint doesn't serve getBlocks, as the slots can't be searched or listed then
+fn listed_window_slots(
+    client: &impl BlockSource,
+    newest_slot: u64,
+    newest_timestamp: i64,
+    window_seconds: i64,
+    oldest_slot: u64
+) -> Result<Option<Vec<u64>>, TpsError> {
+    let start_time = newest_timestamp.saturating_sub(window_seconds);
+    let start_slot = match window_start_slot(client, newest_slot, start_time, oldest_slot) {
+        Ok(start_slot) => start_slot,
+        Err(TpsError::Rpc(err)) if rpc_error_code(&err) == Some(JSON_RPC_METHOD_NOT_FOUND) => {
+            log::info!("The endpoint doesn't serve getBlocks, following parent slots instead");
+            return Ok(None);
+        }
+        //The walk finds out where the cleaned up history ends on its own, and reports it
+        Err(TpsError::BlockCleanedUp(slot)) => {
+            log::debug!("Block in slot {} was cleaned up while searching for the window start", slot);
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(err);
+        }
+    };
+    log::debug!("Window of {} seconds starts at or after slot {}", window_seconds, start_slot);
+
+    match listed_slots(client, start_slot, newest_slot) {
+        Err(TpsError::BlockCleanedUp(slot)) => {
+            log::debug!("Block in slot {} was cleaned up while listing the window", slot);
+            Ok(None)
+        }
+        listed => listed,
+    }
+}
+
+//Function that returns the slots between start and end, both included, that produced a block, in ascending order.
+//They are listed with getBlocks, or found by following parent slots back from the end slot, which then has to
+//have a block, on endpoints that don't serve it
+pub fn produced_slots(client: &impl BlockSource, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+    if let Some(slots) = listed_slots(client, start_slot, end_slot)? {
+        return Ok(slots);
+    }
+
+    let mut slots = Vec::new();
+    let mut slot = end_slot;
+    while slot >= start_slot {
+        let (block, _) = get_block_with_details(client, slot, TransactionDetailLevel::None, false)?;
+        slots.push(slot);
+
+        if block.parent_slot >= slot {
+            break;
+        }
+        slot = block.parent_slot;
+    }
+    slots.reverse();
+
+    Ok(slots)
+}
+
+//Function that tells whether a scan from the tip would count any block after the given slot. The scan starts at
+//least tip_lag slots behind the tip and passes over the warmup blocks first, so a tip just past the slot can still
+//leave nothing new to count
+pub fn has_blocks_after(client: &impl BlockSource, slot: u64, options: &ScanOptions) -> Result<bool, TpsError> {
+    let lagged_slot = client.get_slot()?.saturating_sub(options.tip_lag);
+    if lagged_slot <= slot {
+        return Ok(false);
+    }
+
+    //Without getBlocks every slot up to the lagged one is taken to have produced a block
+    let new_block_count = match listed_slots(client, slot + 1, lagged_slot)? {
+        Some(slots) => slots.len() as u64,
+        None => lagged_slot - slot,
+    };
+
+    Ok(new_block_count > options.warmup_blocks)
+}
+
+//Function that analyzes a block fetched with the given transaction detail. Without full detail only
+//the number of transactions is known, not what they are
+fn analyze_fetched_block(
+    slot: u64,
+    block: &EncodedConfirmedBlock,
+    transaction_count: Option<u64>,
+    transaction_details: TransactionDetailLevel,
+    options: &AnalysisOptions
+) -> BlockAnalysis {
+    set_current_slot(Some(slot));
+
+    match transaction_details {
+        TransactionDetailLevel::Full => analyze_block(block, options),
+        //Only signatures still tell an empty block apart, without transactions the count is unknown
+        _ => BlockAnalysis {
+            total_transactions: transaction_count.unwrap_or(0),
+            reward_only_blocks: u64::from(transaction_count == Some(0) && !block.rewards.is_empty()),
+            ..BlockAnalysis::default()
+        },
+    }
+}
+
+//Function that tells whether the scan fetches blocks with their rewards. Blocks kept for replay are fetched
+//whole, so a replay can still add up their rewards, and the rewards metric needs them too
+fn fetches_rewards(options: &ScanOptions) -> bool {
+    !options.skip_rewards || options.dump_blocks.is_some() || options.metrics.contains(&MetricKind::Rewards)
+}
+
+//Function that returns the transaction detail the scan fetches blocks with. Options that inspect
+//instructions, or keep the blocks for replay, fall back to full detail
+fn detail_level(options: &ScanOptions) -> TransactionDetailLevel {
+    if options.transaction_details == TransactionDetailLevel::Full {
+        return TransactionDetailLevel::Full;
+    }
+
+    let analysis = &options.analysis;
+    let needs_instructions = analysis.include_inner ||
+        analysis.discriminator_program.is_some() ||
+        !analysis.program_filter.include_only.is_empty() ||
+        !analysis.program_filter.exclude.is_empty() ||
+        analysis.large_transfer_threshold.is_some() ||
+        !analysis.counted_instructions.is_empty() ||
+        options.weighted ||
+        options.top_signers.is_some() ||
+        options.dump_blocks.is_some();
+
+    if needs_instructions {
+        log::warn!(
+            "Fetching full transaction details instead of {:?}, the requested options inspect instructions",
+            options.transaction_details
+        );
+        return TransactionDetailLevel::Full;
+    }
+
+    options.transaction_details
+}
+
+//Slots just below the tip sampled to see how many of them were skipped by their leader
+const SKIP_RATE_SAMPLE_SLOTS: u64 = 150;
+
+//Function that estimates how many blocks and RPC calls a scan of the window would take, and how long.
+//The slot rate comes from the node's recent performance samples, scaled by the share of recent slots that
+//produced a block, and the per-call latency is measured by fetching the newest block once. A source held to
+//a rate limit, like an endpoint pool, can't be called any faster than the limit allows
+pub fn estimate_scan_cost(
+    client: &(impl BlockSource + PerformanceSamples),
+    window_seconds: i64
+) -> Result<ScanEstimate, TpsError> {
+    let slots_per_second = client.recent_slots_per_second()?;
+    let newest_block_number = client.get_slot()?;
+    let blocks_per_second = slots_per_second * produced_slot_share(client, newest_block_number)?;
+    log::debug!("Block production rate: {} blocks per second", blocks_per_second);
+
+    let call_start = Instant::now();
+    get_block(client, newest_block_number)?;
+    let mut seconds_per_call = call_start.elapsed().as_secs_f64();
+    log::debug!("Measured get_block latency: {} seconds", seconds_per_call);
+    if let Some(max_requests_per_second) = client.max_requests_per_second() {
+        seconds_per_call = seconds_per_call.max(1.0 / max_requests_per_second);
+    }
+
+    let estimated_blocks = ((window_seconds.max(0) as f64) * blocks_per_second).ceil() as u64;
+
+    //One call for the newest block, plus one for the parent of every block in the window
+    let estimated_calls = estimated_blocks.saturating_add(1);
+
+    Ok(ScanEstimate {
+        estimated_blocks,
+        estimated_calls,
+        estimated_seconds: (estimated_calls as f64) * seconds_per_call,
+    })
+}
+
+//Function that returns the share of the slots just below the given one that produced a block, as skipped
+//slots are counted by the performance samples but have no block to scan
+fn produced_slot_share(client: &impl BlockSource, newest_slot: u64) -> Result<f64, TpsError> {
+    let oldest_slot = newest_slot.saturating_sub(SKIP_RATE_SAMPLE_SLOTS - 1);
+    let sampled_slots = newest_slot - oldest_slot + 1;
+    let produced = client.get_blocks(oldest_slot, newest_slot)?.len();
+    let share = (produced as f64) / (sampled_slots as f64);
+    log::debug!("{} of the {} slots up to {} produced a block", produced, sampled_slots, newest_slot);
+
+    Ok(share)
+}
+
+//Function that asks the RPC node whether it is healthy, i.e. caught up with the cluster. A node that is
+//behind says by how many slots, which becomes the reason of the error
+pub fn check_node_health(client: &RpcClient, endpoint: &str) -> Result<(), TpsError> {
+    let err = match client.get_health() {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+
+    match err.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. })
+            if *code == rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => {
+            Err(TpsError::NodeUnhealthy {
+                endpoint: endpoint.to_string(),
+                reason: message.clone(),
+            })
+        }
+        _ => Err(err.into()),
+    }
+}
+
+//Length of one of the RPC node's performance samples, in seconds
+const PERFORMANCE_SAMPLE_SECONDS: i64 = 60;
+
+//The RPC node keeps at most this many performance samples
+const MAX_PERFORMANCE_SAMPLES: usize = 720;
+
+//Function that fetches the RPC node's own TPS over its most recent performance samples, taking as many
+//as it takes to cover the given window. Samples are a minute long, so the covered span is rounded up to that
+pub fn fetch_official_tps(client: &RpcClient, window_seconds: i64) -> Result<OfficialTps, TpsError> {
+    let sample_count = (window_seconds.max(1) as f64 / PERFORMANCE_SAMPLE_SECONDS as f64).ceil() as usize;
+    let samples = client.get_recent_performance_samples(Some(sample_count.min(MAX_PERFORMANCE_SAMPLES)))?;
+
+    let sample_seconds: u64 = samples.iter().map(|sample| u64::from(sample.sample_period_secs)).sum();
+    let transactions: u64 = samples.iter().map(|sample| sample.num_transactions).sum();
+    let non_vote_transactions: Option<u64> = samples.iter().map(|sample| sample.num_non_vote_transactions).sum();
+
+    Ok(OfficialTps {
+        samples: samples.len(),
+        sample_seconds,
+        transactions_per_second: tps_from_span(transactions, sample_seconds as f64),
+        non_vote_transactions_per_second: non_vote_transactions.map(|non_vote_transactions| {
+            tps_from_span(non_vote_transactions, sample_seconds as f64)
+        }),
+    })
+}
+
+//Function for looping through blocks, counting the total number of transactions. And then finally doing the transactions per second calculation
+pub fn calculate_for_range(
+    client: &impl BlockSource,
+    window: ScanWindow,
+    options: &ScanOptions
+) -> Result<TpsReport, TpsError> {
+    calculate_for_range_with_clock(client, window, options, &SystemClock)
+}
+
+//Function that runs the same scan as calculate_for_range, measuring its runtime and duration with the given clock
+pub fn calculate_for_range_with_clock(
+    client: &impl BlockSource,
+    window: ScanWindow,
+    options: &ScanOptions,
+    clock: &impl Clock
+) -> Result<TpsReport, TpsError> {
+    let calculation_start = clock.now();
+
+    match window {
+        ScanWindow::Seconds(seconds) if seconds < 0 => {
+            return Err(TpsError::InvalidWindow(format!("window of {} seconds is negative", seconds)));
+        }
+        ScanWindow::LastBlocks(0) => {
+            return Err(TpsError::InvalidWindow("window of 0 blocks covers nothing".to_string()));
+        }
+        _ => {}
+    }
+
+    let transaction_details = detail_level(options);
+    let rewards = fetches_rewards(options);
+
+    let mut tip_slot = None;
+    let (mut newest_block_number, (mut current_block, mut current_transaction_count)) = match options.seed_slot {
+        Some(seed_slot) => {
+            log::debug!("Starting from seed slot: {}", seed_slot);
+            let seed_block = get_block_with_details(client, seed_slot, transaction_details, rewards)?;
+            if seed_block.0.block_time.is_none() {
+                return Err(TpsError::MissingBlockTime(seed_slot));
+            }
+            (seed_slot, seed_block)
+        }
+        None => {
+            let live_tip_slot = client.get_slot()?;
+            tip_slot = Some(live_tip_slot);
+
+            let newest_block = settled_block_behind_tip(
+                client,
+                live_tip_slot,
+                options.tip_lag,
+                transaction_details,
+                rewards
+            )?;
+            if newest_block.0 != live_tip_slot {
+                log::info!(
+                    "Starting from slot {}, {} slots behind the tip {}",
+                    newest_block.0,
+                    live_tip_slot - newest_block.0,
+                    live_tip_slot
+                );
+            }
+            newest_block
+        }
+    };
+
+    //The blocks nearest the tip are often still filling up. Passing over them before the walk starts keeps
+    //both their transactions and their timestamps out of the window, which then ends a little further back
+    for _ in 0..options.warmup_blocks {
+        let parent_slot = current_block.parent_slot;
+        log::debug!("Skipping warmup block in slot {}", newest_block_number);
+        (current_block, current_transaction_count) = get_block_with_details(
+            client,
+            parent_slot,
+            transaction_details,
+            rewards
+        )?;
+        newest_block_number = parent_slot;
+    }
+    let mut current_block_number = newest_block_number;
+
+    //Blocks older than this have been pruned from the endpoint's ledger and can't be fetched
+    let first_available_block = match client.get_first_available_block() {
+        Ok(first_available_block) => Some(first_available_block),
+        Err(err) => {
+            log::warn!("Could not fetch the first available block: {}", err);
+            None
+        }
+    };
+
+    //A range of slots is known up front, or found by block time, so the blocks it produced are listed instead of
+    //found one parent at a time. The walk then steps through the list, and only follows the parent slot below its
+    //start
+    let mut produced_slots = match window {
+        ScanWindow::Seconds(window_seconds) => {
+            let newest_timestamp = current_block.block_time.ok_or(TpsError::MissingBlockTime(newest_block_number))?;
+            listed_window_slots(
+                client,
+                newest_block_number,
+                newest_timestamp,
+                window_seconds,
+                first_available_block.unwrap_or(0)
+            )?.unwrap_or_default()
+        }
+        ScanWindow::SinceSlot(start_slot) => {
+            if start_slot > newest_block_number {
+                return Err(
+                    TpsError::InvalidWindow(
+                        format!("start slot {} is newer than the newest block {}", start_slot, newest_block_number)
+                    )
+                );
+            }
+            listed_slots(client, start_slot, newest_block_number)?.unwrap_or_default()
+        }
+        _ => Vec::new(),
+    };
+    produced_slots.retain(|slot| *slot < newest_block_number);
+
+    let mut slot_dump = options.dump_slots
+        .as_deref()
+        .map(SlotDump::create)
+        .transpose()?;
+    if let Some(slot_dump) = &mut slot_dump {
+        slot_dump.write_block(newest_block_number, current_block.block_time)?;
+    }
+
+    let mut block_dump = options.dump_blocks
+        .as_deref()
+        .map(BlockDump::create)
+        .transpose()?;
+    if let Some(block_dump) = &mut block_dump {
+        block_dump.write_block(newest_block_number, &current_block)?;
+    }
+
+    let mut truncated_by_pruning = false;
+    let mut truncated_by_cleanup = false;
+    let mut time_limited = false;
+    let mut window_completed = false;
+
+    let newest_timestamp = current_block.block_time.ok_or(TpsError::MissingBlockTime(newest_block_number))?;
+    let stop_conditions = stop_conditions(window, newest_timestamp, options.max_blocks)?;
+
+    let mut counted_blocks: Vec<(u64, FetchedBlock)> = Vec::new();
+    let mut window_totals = WindowTotals::new(transaction_details, options);
+    let mut blocks_scanned: u64 = 0;
+    let mut last_flush = calculation_start;
+    //Slots between every counted block and its parent, the time-weighted average's span
+    let mut counted_slots: u64 = 0;
+    let mut analyzed_slots: HashSet<u64> = HashSet::new();
+    let mut oldest_scanned_block_number = newest_block_number;
+
+    //Loop through the blocks, starting from the newest block, and going back in time
+    let (oldest_timestamp, oldest_block_number, boundary_slot) = loop {
+        let prev_block_number = match produced_slots.pop() {
+            Some(produced_slot) => {
+                if produced_slot != current_block.parent_slot {
+                    log::debug!(
+                        "Block in slot {} names slot {} as its parent, the listed previous block is in slot {}",
+                        current_block_number,
+                        current_block.parent_slot,
+                        produced_slot
+                    );
+                }
+                produced_slot
+            }
+            None => current_block.parent_slot,
+        };
+
+        //Some endpoints return parent chains that loop back around forks or duplicate slots. Walking on would
+        //count the same blocks again, so the walk ends before the block pointing at an already analyzed slot
+        if prev_block_number == current_block_number || analyzed_slots.contains(&prev_block_number) {
+            log::warn!(
+                "Block in slot {} points back to the already analyzed slot {}, stopping the walk",
+                current_block_number,
+                prev_block_number
+            );
+            let current_block_timestamp = current_block.block_time.ok_or(
+                TpsError::MissingBlockTime(current_block_number)
+            )?;
+            break (current_block_timestamp, oldest_scanned_block_number, current_block_number);
+        }
+
+        //A parent older than the first available block has been pruned. The endpoint may also have pruned it
+        //since that was fetched, in which case fetching it reports that no block is available
+        let pruned = first_available_block.is_some_and(|first_available_block| {
+            prev_block_number < first_available_block
+        });
+        //A cleaned up block is a pruning boundary too, but one that is only found out by asking for it
+        let mut cleaned_up = false;
+        let prev_block = if pruned {
+            None
+        } else {
+            match get_block_with_details(client, prev_block_number, transaction_details, rewards) {
+                Ok(prev_block) => Some(prev_block),
+                Err(TpsError::NoBlockAvailable(_)) => None,
+                Err(TpsError::BlockCleanedUp(_)) => {
+                    cleaned_up = true;
+                    None
+                }
+                Err(err) => {
+                    return Err(err);
+                }
+            }
+        };
+
+        //Without the parent block the current block's time span is unknown, so it becomes the window's boundary
+        let (prev_block, prev_transaction_count) = match prev_block {
+            Some(prev_block) => prev_block,
+            None => {
+                let current_block_timestamp = current_block.block_time.ok_or(
+                    TpsError::MissingBlockTime(current_block_number)
+                )?;
+                let covered_seconds = newest_timestamp.saturating_sub(current_block_timestamp);
+                let cause = if cleaned_up { "ledger cleanup" } else { "ledger pruning" };
+
+                match options.on_insufficient_history {
+                    InsufficientHistory::Error => {
+                        return Err(TpsError::InsufficientHistory {
+                            slot: prev_block_number,
+                            covered_seconds,
+                        });
+                    }
+                    InsufficientHistory::Truncate => {
+                        log::info!(
+                            "Window truncated by {}: slot {} is unavailable, covered {} seconds",
+                            cause,
+                            prev_block_number,
+                            covered_seconds
+                        );
+                    }
+                    InsufficientHistory::Warn => {
+                        log::warn!(
+                            "Window truncated by {}: slot {} is unavailable, covered {} seconds",
+                            cause,
+                            prev_block_number,
+                            covered_seconds
+                        );
+                    }
+                }
+
+                if cleaned_up {
+                    truncated_by_cleanup = true;
+                } else {
+                    truncated_by_pruning = true;
+                }
+                break (current_block_timestamp, oldest_scanned_block_number, current_block_number);
+            }
+        };
+
+        if let Some(slot_dump) = &mut slot_dump {
+            slot_dump.write_skipped_between(prev_block_number, current_block_number)?;
+            slot_dump.write_block(prev_block_number, prev_block.block_time)?;
+        }
+        if let Some(block_dump) = &mut block_dump {
+            block_dump.write_block(prev_block_number, &prev_block)?;
+        }
+
+        let counted_timestamp = current_block.block_time.ok_or(TpsError::MissingBlockTime(current_block_number))?;
+        if let Some(block_time) = block_timestamp(&current_block) {
+            log::debug!("Block time: {}", block_time.format("%Y-%m-%d %H:%M:%S"));
+        }
+
+        //The walk carries on from the parent, so the counted block can be handed off instead of copied
+        let counted_block = mem::replace(&mut current_block, prev_block);
+        let counted_transaction_count = mem::replace(&mut current_transaction_count, prev_transaction_count);
+        if options.parallel_counting {
+            counted_blocks.push((current_block_number, (counted_block, counted_transaction_count)));
+        } else {
+            window_totals.add(
+                counted_timestamp,
+                analyze_fetched_block(
+                    current_block_number,
+                    &counted_block,
+                    counted_transaction_count,
+                    transaction_details,
+                    &options.analysis
+                )
+            );
+        }
+        blocks_scanned += 1;
+        counted_slots += current_block_number.saturating_sub(prev_block_number);
+        analyzed_slots.insert(current_block_number);
+        oldest_scanned_block_number = current_block_number;
+
+        let prev_block = &current_block;
+        let prev_block_timestamp = prev_block.block_time.ok_or(TpsError::MissingBlockTime(prev_block_number))?;
+
+        if let (Some(flush_output), Some(flush_interval)) = (&options.flush_output, options.flush_interval) {
+            let now = clock.now();
+            if now.signed_duration_since(last_flush).to_std().unwrap_or_default() >= flush_interval {
+                last_flush = now;
+
+                //Blocks left for parallel counting haven't been analyzed yet, the snapshot analyzes them itself
+                let snapshot_totals;
+                let totals = if options.parallel_counting {
+                    snapshot_totals = count_blocks(&counted_blocks, transaction_details, options, false);
+                    &snapshot_totals
+                } else {
+                    &window_totals
+                };
+                let slot_span = newest_block_number.saturating_sub(current_block_number);
+                let counted = CountedWindow {
+                    window,
+                    oldest_timestamp: prev_block_timestamp,
+                    newest_timestamp,
+                    oldest_slot: current_block_number,
+                    boundary_slot: prev_block_number,
+                    newest_slot: newest_block_number,
+                    blocks_scanned,
+                    counted_slots,
+                    slots_in_range: slot_span.saturating_add(1),
+                    tip_slot,
+                    window_completed: false,
+                    truncated_by_pruning,
+                    truncated_by_cleanup,
+                    time_limited,
+                    counting_threads: 1,
+                };
+                let mut partial_report = window_report(
+                    counted,
+                    totals,
+                    transaction_details,
+                    options,
+                    clock,
+                    calculation_start
+                );
+                partial_report.partial = true;
+
+                match write_json_report(flush_output, &partial_report) {
+                    Ok(()) => log::debug!("Flushed the partial report after {} blocks", blocks_scanned),
+                    Err(err) => log::warn!("Could not flush the partial report to {}: {}", flush_output.display(), err),
+                }
+            }
+        }
+
+        let state = WalkState {
+            blocks_scanned,
+            parent_slot: prev_block_number,
+            parent_timestamp: prev_block_timestamp,
+            parent_block_height: prev_block.block_height,
+        };
+
+        if let Some(condition) = stop_conditions.iter().find(|condition| condition.is_met(&state)) {
+            log::debug!("Stopping the walk: {:?}", condition);
+            window_completed = condition.completes_window();
+            break (prev_block_timestamp, current_block_number, prev_block_number);
+        }
+
+        if let Some(max_runtime) = options.max_runtime {
+            let runtime = clock.now().signed_duration_since(calculation_start).to_std().unwrap_or_default();
+            if runtime >= max_runtime {
+                log::warn!(
+                    "Scan stopped after reaching the maximum runtime of {} seconds",
+                    max_runtime.as_secs()
+                );
+                time_limited = true;
+                break (prev_block_timestamp, current_block_number, prev_block_number);
+            }
+        }
+
+        current_block_number = prev_block_number;
+    };
+
+    let (window_totals, counting_threads) = if options.parallel_counting {
+        (count_blocks(&counted_blocks, transaction_details, options, true), rayon::current_num_threads())
+    } else {
+        (window_totals, 1)
+    };
+
+    let slot_span = newest_block_number.saturating_sub(oldest_block_number);
+    let counted = CountedWindow {
+        window,
+        oldest_timestamp,
+        newest_timestamp,
+        oldest_slot: oldest_block_number,
+        boundary_slot,
+        newest_slot: newest_block_number,
+        blocks_scanned,
+        counted_slots,
+        slots_in_range: if blocks_scanned == 0 { 0 } else { slot_span.saturating_add(1) },
+        tip_slot,
+        window_completed,
+        truncated_by_pruning,
+        truncated_by_cleanup,
+        time_limited,
+        counting_threads,
+    };
+
+    Ok(window_report(counted, &window_totals, transaction_details, options, clock, calculation_start))
+}
+
+//Running totals of the blocks a scan counted. Every block is summed up as soon as it is analyzed, so the scan
+//doesn't hold an analysis per block, only its time and transaction counts for the buckets
+struct WindowTotals {
+    totals: BlockAnalysis,
+    //(block time, transactions counted toward TPS, user transactions) of every counted block, in walk order
+    blocks: Vec<(i64, u64, u64)>,
+    sizes: SizeStats,
+    //User transactions per fee payer, only kept when the busiest signers are reported
+    signers: Option<SignerCounts>,
+    metrics: Vec<(MetricKind, Box<dyn Metric>)>,
+    //Without full detail vote and user transactions can't be told apart, so TPS counts all of them
+    full_detail: bool,
+}
+
+impl WindowTotals {
+    fn new(transaction_details: TransactionDetailLevel, options: &ScanOptions) -> Self {
+        let full_detail = transaction_details == TransactionDetailLevel::Full;
+
+        WindowTotals {
+            totals: BlockAnalysis::default(),
+            blocks: Vec::new(),
+            sizes: SizeStats::new(options.streaming.then_some(STREAMING_SIZE_SAMPLE)),
+            signers: options.top_signers.map(|_| SignerCounts::default()),
+            metrics: options.metrics
+                .iter()
+                .map(|kind| (*kind, kind.build(full_detail)))
+                .collect(),
+            full_detail,
+        }
+    }
+
+    fn add(&mut self, block_time: i64, mut analysis: BlockAnalysis) {
+        let counted = if self.full_detail { analysis.user_transactions } else { analysis.total_transactions };
+        self.blocks.push((block_time, counted, analysis.user_transactions));
+
+        for (_, metric) in &mut self.metrics {
+            metric.observe(block_time, &analysis);
+        }
+        for size in analysis.transaction_sizes.drain(..) {
+            self.sizes.add(size);
+        }
+        let fee_payer_counts = mem::take(&mut analysis.fee_payer_counts);
+        if let Some(signers) = &mut self.signers {
+            signers.add(fee_payer_counts);
+        }
+        self.totals.add(analysis);
+    }
+}
+
+//Function that analyzes the blocks kept for parallel counting, on all threads or one, and sums them up in order
+fn count_blocks(
+    counted_blocks: &[(u64, FetchedBlock)],
+    transaction_details: TransactionDetailLevel,
+    options: &ScanOptions,
+    parallel: bool
+) -> WindowTotals {
+    let analyze = |(slot, (block, transaction_count)): &(u64, FetchedBlock)| {
+        analyze_fetched_block(*slot, block, *transaction_count, transaction_details, &options.analysis)
+    };
+    let block_analyses: Vec<BlockAnalysis> = if parallel {
+        counted_blocks.par_iter().map(analyze).collect()
+    } else {
+        counted_blocks.iter().map(analyze).collect()
+    };
+
+    let mut totals = WindowTotals::new(transaction_details, options);
+    for ((_, (block, _)), analysis) in counted_blocks.iter().zip(block_analyses) {
+        totals.add(block.block_time.unwrap_or_default(), analysis);
+    }
+
+    totals
+}
+
+//What a scan found over its window, before it is summed up into a report
+struct CountedWindow {
+    window: ScanWindow,
+    //Block times the window spans, and its oldest and newest scanned slot
+    oldest_timestamp: i64,
+    newest_timestamp: i64,
+    oldest_slot: u64,
+    //Slot of the block the oldest timestamp was taken from
+    boundary_slot: u64,
+    newest_slot: u64,
+    blocks_scanned: u64,
+    //Slots between every counted block and its previous one, the time-weighted average's span
+    counted_slots: u64,
+    //Slots that could have held a scanned block, so the ones without a block count as skipped
+    slots_in_range: u64,
+    tip_slot: Option<u64>,
+    window_completed: bool,
+    truncated_by_pruning: bool,
+    truncated_by_cleanup: bool,
+    time_limited: bool,
+    counting_threads: usize,
+}
+
+//Function that sums up what a scan counted into its report
+fn window_report(
+    counted: CountedWindow,
+    window_totals: &WindowTotals,
+    transaction_details: TransactionDetailLevel,
+    options: &ScanOptions,
+    clock: &impl Clock,
+    calculation_start: DateTime<Utc>
+) -> TpsReport {
+    let CountedWindow {
+        window,
+        oldest_timestamp,
+        newest_timestamp,
+        oldest_slot,
+        boundary_slot,
+        newest_slot,
+        blocks_scanned,
+        counted_slots,
+        slots_in_range,
+        tip_slot,
+        window_completed,
+        truncated_by_pruning,
+        truncated_by_cleanup,
+        time_limited,
+        counting_threads,
+    } = counted;
+    let totals = window_totals.totals.clone();
+    let full_detail = window_totals.full_detail;
+
+    let buckets = match options.bucket_seconds {
+        Some(bucket_seconds) => bucket_tps(&window_totals.blocks, oldest_timestamp, newest_timestamp, bucket_seconds),
+        None => Vec::new(),
+    };
+    let counted_timestamps: Vec<i64> = window_totals.blocks.iter().map(|(block_time, _, _)| *block_time).collect();
+    let intervals = block_intervals(&counted_timestamps);
+    let trimmed_transactions_per_second = if options.trim_boundaries {
+        let trimmed_blocks: Vec<(i64, u64)> = window_totals.blocks
+            .iter()
+            .map(|(block_time, counted, _)| (*block_time, *counted))
+            .collect();
+        let trimmed = trimmed_tps(&trimmed_blocks);
+        if trimmed.is_none() {
+            log::warn!(
+                "Only {} blocks were counted, too few to trim the boundary blocks off, at least {} are needed",
+                trimmed_blocks.len(),
+                MIN_TRIMMED_BLOCKS
+            );
+        }
+        trimmed
+    } else {
+        None
+    };
+    let metric_window = MetricWindow { oldest_timestamp, newest_timestamp };
+    let metrics: BTreeMap<String, serde_json::Value> = window_totals.metrics
+        .iter()
+        .map(|(kind, metric)| (kind.name().to_string(), metric.finish(metric_window)))
+        .collect();
+    let counted_transactions = if full_detail { totals.user_transactions } else { totals.total_transactions };
+    let transactions_per_second = options.tps_average_method.tps(
+        oldest_timestamp,
+        newest_timestamp,
+        counted_transactions,
+        counted_slots
+    );
+    //Every counted instruction is reported, including those no transaction called
+    let counted_instruction_counts = options.analysis.counted_instructions
+        .iter()
+        .map(|counted| {
+            let label = counted.label();
+            let count = totals.counted_instruction_counts.get(&label).copied().unwrap_or(0);
+            (label, count)
+        })
+        .collect();
+    let listed_program_counts = options.analysis.program_filter.include_only
+        .iter()
+        .map(|program_id| {
+            (*program_id, totals.program_transaction_counts.get(program_id).copied().unwrap_or(0))
+        })
+        .collect();
+    let vote_transactions = if full_detail {
+        totals.total_transactions
+            .saturating_sub(totals.user_transactions)
+            .saturating_sub(totals.empty_transactions)
+            .saturating_sub(totals.filtered_out)
+    } else {
+        0
+    };
+
+    let time_span_seconds = newest_timestamp.saturating_sub(oldest_timestamp);
+    let top_programs_by_tps = rank_programs_by_tps(
+        &totals.program_transaction_counts,
+        time_span_seconds as f64,
+        TOP_PROGRAMS
+    );
+
+    //Slots between the oldest and newest scanned block that did not produce a block
+    let slot_span = newest_slot.saturating_sub(oldest_slot);
+    let skipped_slots = slots_in_range.saturating_sub(blocks_scanned);
+    let skip_rate = if slots_in_range == 0 {
+        0.0
+    } else {
+        (skipped_slots as f64) / (slots_in_range as f64)
+    };
+
+    let calculation_end = clock.now();
+
+    let duration = calculation_end.signed_duration_since(calculation_start).to_std().unwrap_or_default();
+
+    TpsReport {
+        schema_version: REPORT_SCHEMA_VERSION,
+        window,
+        transactions_per_second,
+        unit: TpsUnit::default(),
+        tps_average_method: options.tps_average_method,
+        weighted_transactions_per_second: options.weighted.then(|| {
+            calculate_tps(oldest_timestamp, newest_timestamp, totals.distinct_fee_payers)
+        }),
+        trimmed_transactions_per_second,
+        metrics,
+        total_transactions: totals.total_transactions,
+        user_transactions: totals.user_transactions,
+        vote_transactions,
+        transaction_details,
+        empty_transactions: totals.empty_transactions,
+        filtered_out: totals.filtered_out,
+        successful_user_transactions: totals.successful_user_transactions,
+        landed_rate: landed_rate(totals.successful_user_transactions, totals.user_transactions),
+        vote_to_user_ratio: vote_to_user_ratio(vote_transactions, totals.user_transactions),
+        system_program_transactions: totals.system_program_transactions,
+        durable_nonce_transactions: totals.durable_nonce_transactions,
+        large_transfer_threshold: options.analysis.large_transfer_threshold,
+        large_transfer_count: totals.large_transfer_count,
+        elided_large_transfers: totals.large_transfer_count.saturating_sub(totals.large_transfers.len() as u64),
+        large_transfers: totals.large_transfers,
+        multisig_transactions: totals.multisig_transactions,
+        lookup_table_transactions: totals.lookup_table_transactions,
+        average_signatures: (totals.user_transactions > 0).then(|| {
+            (totals.required_signatures as f64) / (totals.user_transactions as f64)
+        }),
+        total_instructions: totals.total_instructions,
+        instructions_per_second: calculate_tps(oldest_timestamp, newest_timestamp, totals.total_instructions),
+        compute_units: totals.compute_units,
+        compute_units_per_second: calculate_tps(oldest_timestamp, newest_timestamp, totals.compute_units),
+        transaction_sizes: window_totals.sizes.distribution(),
+        total_fees: totals.fees,
+        fee_distribution: FeeDistribution::from_counts(&totals.fee_counts),
+        fees_from_successful: totals.fees_from_successful,
+        fees_from_failed: totals.fees_from_failed,
+        base_fees: totals.base_fees,
+        priority_fees: totals.priority_fees,
+        burned_fees: burned_fees(totals.base_fees),
+        rewards: fetches_rewards(options).then_some(totals.rewards),
+        instruction_counts: totals.instruction_counts,
+        listed_program_counts,
+        program_transaction_counts: totals.program_transaction_counts,
+        top_programs_by_tps,
+        top_signers: match (options.top_signers, &window_totals.signers) {
+            (Some(limit), Some(signers)) => rank_signers(&signers.counts, limit),
+            _ => Vec::new(),
+        },
+        top_signers_approximate: window_totals.signers.as_ref().is_some_and(|signers| signers.pruned),
+        discriminator_program: options.analysis.discriminator_program,
+        discriminator_counts: totals.discriminator_counts,
+        counted_instruction_counts,
+        parallel_counting: options.parallel_counting,
+        counting_threads,
+        blocks_scanned,
+        skipped_slots,
+        skip_rate,
+        reward_only_blocks: totals.reward_only_blocks,
+        tip_slot,
+        warmup_blocks: options.warmup_blocks,
+        newest_slot,
+        oldest_slot,
+        boundary_slot,
+        slot_span,
+        time_span_seconds,
+        counted_slots,
+        //The time span starts at the boundary block, so it covers the counted slots rather than the slot span
+        slot_time_drift: slot_time_drift(time_span_seconds, counted_slots),
+        min_block_interval_ms: intervals.map(|intervals| intervals.min_ms),
+        median_block_interval_ms: intervals.map(|intervals| intervals.median_ms),
+        max_block_interval_ms: intervals.map(|intervals| intervals.max_ms),
+        bucket_seconds: options.bucket_seconds,
+        buckets,
+        window_covered_seconds: time_span_seconds,
+        window_truncated: !window_completed,
+        partial: false,
+        truncated_by_pruning,
+        truncated_by_cleanup,
+        time_limited,
+        oldest_timestamp,
+        newest_timestamp,
+        calculation_duration: duration,
+        official: None,
+    }
+}
+
+//Function that reads a list of slots, one per line, as piped in by another tool. Blank lines and lines starting
+//with # are skipped, as are lines that aren't a slot number, with a warning
+pub fn read_slot_list(reader: impl BufRead) -> Result<Vec<u64>, TpsError> {
+    let mut slots = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.parse::<u64>() {
+            Ok(slot) => slots.push(slot),
+            Err(err) => log::warn!("Skipping line {} of the slot list, {:?} is not a slot: {}", index + 1, line, err),
+        }
+    }
+
+    Ok(slots)
+}
+
+//Function that analyzes exactly the blocks in the given slots, in whatever order and however far apart, and
+//reports TPS over the span of their block times. Every listed block is counted, the oldest one included.
+//Slots without a block are skipped with a warning and reported as skipped slots
+pub fn calculate_for_slots(
+    client: &impl BlockSource,
+    slots: &[u64],
+    options: &ScanOptions
+) -> Result<TpsReport, TpsError> {
+    let clock = SystemClock;
+    let calculation_start = clock.now();
+
+    let slots: BTreeSet<u64> = slots.iter().copied().collect();
+    if slots.is_empty() {
+        return Err(TpsError::InvalidWindow("the slot list is empty".to_string()));
+    }
+
+    let transaction_details = detail_level(options);
+    let rewards = fetches_rewards(options);
+
+    //Without parallel counting every block is summed up as it is fetched, instead of held until the end
+    let mut counted_blocks: Vec<(u64, FetchedBlock)> = Vec::new();
+    let mut window_totals = WindowTotals::new(transaction_details, options);
+    let mut found_slots: Vec<u64> = Vec::new();
+    for slot in &slots {
+        match get_block_with_details(client, *slot, transaction_details, rewards) {
+            Ok((block, transaction_count)) => {
+                let block_time = block.block_time.ok_or(TpsError::MissingBlockTime(*slot))?;
+                if options.parallel_counting {
+                    counted_blocks.push((*slot, (block, transaction_count)));
+                } else {
+                    window_totals.add(
+                        block_time,
+                        analyze_fetched_block(*slot, &block, transaction_count, transaction_details, &options.analysis)
+                    );
+                }
+                found_slots.push(*slot);
+            }
+            Err(TpsError::NoBlockAvailable(_)) | Err(TpsError::BlockCleanedUp(_)) => {
+                log::warn!("Skipping listed slot {}, it has no available block", slot);
+            }
+            Err(err) => {
+                return Err(err);
+            }
+        }
+    }
+
+    let (window_totals, counting_threads) = if options.parallel_counting {
+        (count_blocks(&counted_blocks, transaction_details, options, true), rayon::current_num_threads())
+    } else {
+        (window_totals, 1)
+    };
+
+    let block_times = window_totals.blocks.iter().map(|(block_time, _, _)| *block_time);
+    let oldest_timestamp = block_times.clone().min().unwrap_or_default();
+    let newest_timestamp = block_times.max().unwrap_or_default();
+    let blocks_scanned = found_slots.len() as u64;
+
+    let counted = CountedWindow {
+        window: ScanWindow::ListedSlots(slots.len() as u64),
+        oldest_timestamp,
+        newest_timestamp,
+        oldest_slot: found_slots.first().copied().unwrap_or(0),
+        //The span starts at the oldest listed block's own time, so it is its own boundary
+        boundary_slot: found_slots.first().copied().unwrap_or(0),
+        newest_slot: found_slots.last().copied().unwrap_or(0),
+        blocks_scanned,
+        //Each listed block stands for its own slot, whatever lies between them
+        counted_slots: blocks_scanned,
+        slots_in_range: slots.len() as u64,
+        tip_slot: None,
+        window_completed: true,
+        truncated_by_pruning: false,
+        truncated_by_cleanup: false,
+        time_limited: false,
+        counting_threads,
+    };
+
+    Ok(window_report(counted, &window_totals, transaction_details, options, &clock, calculation_start))
+}
+
+//Function that runs the full scan over blocks loaded from a per-block NDJSON or JSON file, without an RPC endpoint.
+//The newest block in the file is where the walk starts, unless the options give a seed slot
+pub fn calculate_from_file(path: &Path, window: ScanWindow, options: &ScanOptions) -> Result<TpsReport, TpsError> {
+    let source = FileBlockSource::open(path)?;
+
+    calculate_for_range(&source, window, options)
+}
+
+//Function that runs the same scan over each of the given per-block files, at most the given number at a time.
+//The results come back in the order of the paths, and a file that fails doesn't stop the others
+pub fn calculate_batch(
+    paths: &[PathBuf],
+    window: ScanWindow,
+    options: &ScanOptions,
+    jobs: usize
+) -> Vec<Result<TpsReport, TpsError>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .expect("thread pool for the batch can be built");
+
+    pool.install(|| {
+        paths
+            .par_iter()
+            .map(|path| calculate_from_file(path, window, options))
+            .collect()
+    })
+}
+
+//Function that calculates TPS over several time windows, all ending at the same newest block, while fetching
+//every block only once. The widest window is scanned first and the narrower ones reuse its blocks.
+//The reports come back in the order the windows were given
+pub fn calculate_for_windows(
+    client: &impl BlockSource,
+    window_seconds: &[i64],
+    options: &ScanOptions
+) -> Result<Vec<TpsReport>, TpsError> {
+    let collected = CollectedBlocks::new(client);
+
+    let mut widest_first: Vec<usize> = (0..window_seconds.len()).collect();
+    widest_first.sort_by_key(|&index| std::cmp::Reverse(window_seconds[index]));
+
+    let mut reports: Vec<Option<TpsReport>> = window_seconds.iter().map(|_| None).collect();
+    for (position, &index) in widest_first.iter().enumerate() {
+        //The widest scan already wrote every block the narrower ones see, so only it writes the dumps
+        let window_options = if position == 0 {
+            options.clone()
+        } else {
+            ScanOptions {
+                dump_slots: None,
+                dump_blocks: None,
+                ..options.clone()
+            }
+        };
+
+        reports[index] = Some(calculate_for_range(&collected, ScanWindow::Seconds(window_seconds[index]), &window_options)?);
+    }
+
+    Ok(reports.into_iter().flatten().collect())
+}
+
+//Block source that keeps every block fetched through it in memory, and keeps reporting the slot it saw first
+//as the newest one. Scans over it all end at the same block and only ever fetch a block once
+struct CollectedBlocks<'a, S> {
+    inner: &'a S,
+    newest_slot: Cell<Option<u64>>,
+    //None for slots the inner source had no block for
+    blocks: RefCell<HashMap<u64, Option<FetchedBlock>>>,
+}
+
+impl<'a, S: BlockSource> CollectedBlocks<'a, S> {
+    fn new(inner: &'a S) -> Self {
+        CollectedBlocks {
+            inner,
+            newest_slot: Cell::new(None),
+            blocks: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<S: BlockSource> BlockSource for CollectedBlocks<'_, S> {
+    fn get_slot(&self) -> Result<u64, TpsError> {
+        if let Some(newest_slot) = self.newest_slot.get() {
+            return Ok(newest_slot);
+        }
+
+        let newest_slot = self.inner.get_slot()?;
+        self.newest_slot.set(Some(newest_slot));
+        Ok(newest_slot)
+    }
+
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+        self.get_block_with_details(slot, TransactionDetailLevel::Full, true).map(|(block, _)| block)
+    }
+
+    //Every scan over the source uses the same detail level, so blocks are kept by slot alone
+    fn get_block_with_details(
+        &self,
+        slot: u64,
+        details: TransactionDetailLevel,
+        rewards: bool
+    ) -> Result<(EncodedConfirmedBlock, Option<u64>), TpsError> {
+        if let Some(collected) = self.blocks.borrow().get(&slot) {
+            return match collected {
+                Some((block, transaction_count)) => Ok((clone_block(block), *transaction_count)),
+                None => Err(TpsError::NoBlockAvailable(slot)),
+            };
+        }
+
+        match self.inner.get_block_with_details(slot, details, rewards) {
+            Ok((block, transaction_count)) => {
+                self.blocks.borrow_mut().insert(slot, Some((clone_block(&block), transaction_count)));
+                Ok((block, transaction_count))
+            }
+            Err(TpsError::NoBlockAvailable(slot)) => {
+                self.blocks.borrow_mut().insert(slot, None);
+                Err(TpsError::NoBlockAvailable(slot))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn get_block_time(&self, slot: u64) -> Result<Option<i64>, TpsError> {
+        match self.blocks.borrow().get(&slot) {
+            Some(Some((block, _))) => Ok(block.block_time),
+            Some(None) => Err(TpsError::NoBlockAvailable(slot)),
+            None => self.inner.get_block_time(slot),
+        }
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        self.inner.get_blocks(start_slot, end_slot)
+    }
+
+    fn get_first_available_block(&self) -> Result<u64, TpsError> {
+        self.inner.get_first_available_block()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_transaction_status::Reward;
+
+    use super::*;
+    use crate::records::FileBlockSource;
+    use crate::testutil::{ block, chain, source, user_tx, MockClock };
+
+    #[test]
+    fn window_of_empty_blocks_reports_zero_tps() {
+        let source = source(chain(21, 1_000));
+
+        let report = calculate_for_range(&source, ScanWindow::Seconds(10), &ScanOptions::default()).unwrap();
+
+        assert_eq!(report.blocks_scanned, 10);
+        assert_eq!(report.total_transactions, 0);
+        assert_eq!(report.user_transactions, 0);
+        assert_eq!(report.transactions_per_second, 0.0);
+    }
+
+    #[test]
+    fn window_older_than_first_available_block_is_truncated_by_pruning() {
+        //Slots below 50 have been pruned, so the window can't reach back its full 100 seconds
+        let source = source(chain(61, 1_000).into_iter().skip(50).collect());
+
+        let report = calculate_for_range(&source, ScanWindow::Seconds(100), &ScanOptions::default()).unwrap();
+
+        assert!(report.truncated_by_pruning);
+        assert!(!report.truncated_by_cleanup);
+        //The first available block only marks where the window starts, as its own parent is gone
+        assert_eq!((report.oldest_slot, report.newest_slot), (51, 60));
+    }
+
+    #[test]
+    fn window_older_than_first_available_block_fails_when_asked_to() {
+        let source = source(chain(61, 1_000).into_iter().skip(50).collect());
+        let options = ScanOptions {
+            on_insufficient_history: InsufficientHistory::Error,
+            ..ScanOptions::default()
+        };
+
+        let result = calculate_for_range(&source, ScanWindow::Seconds(100), &options);
+
+        assert!(matches!(result, Err(TpsError::InsufficientHistory { slot: 49, covered_seconds: 10 })));
+    }
+
+    #[test]
+    fn looping_parent_chain_counts_every_block_once() {
+        let program_id = Pubkey::new_unique();
+        let mut blocks = chain(11, 1_000);
+        for block in &mut blocks {
+            block.transactions.push(user_tx(program_id));
+        }
+        //Slot 8 names the newest block as its parent, so following parents would go around again. A block
+        //window isn't listed up front, so the walk does follow them
+        blocks[8].parent_slot = 10;
+
+        let report = calculate_for_range(&source(blocks), ScanWindow::LastBlocks(10), &ScanOptions::default())
+            .unwrap();
+
+        assert_eq!((report.oldest_slot, report.newest_slot), (9, 10));
+        assert_eq!(report.user_transactions, 2);
+        assert!(report.window_truncated);
+    }
+
+    //Source whose endpoint has cleaned up every block up to a slot without advertising it as its first one
+    struct CleanedUpSource {
+        inner: FileBlockSource,
+        cleaned_up_through: u64,
+    }
+
+    impl BlockSource for CleanedUpSource {
+        fn get_slot(&self) -> Result<u64, TpsError> {
+            self.inner.get_slot()
+        }
+
+        fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+            if slot <= self.cleaned_up_through {
+                return Err(TpsError::BlockCleanedUp(slot));
+            }
+            self.inner.get_block(slot)
+        }
+
+        fn get_first_available_block(&self) -> Result<u64, TpsError> {
+            self.inner.get_first_available_block()
+        }
+    }
+
+    #[test]
+    fn cleaned_up_block_truncates_the_window() {
+        let source = CleanedUpSource {
+            inner: source(chain(11, 1_000)),
+            cleaned_up_through: 6,
+        };
+
+        let report = calculate_for_range(&source, ScanWindow::Seconds(60), &ScanOptions::default()).unwrap();
+
+        assert!(report.truncated_by_cleanup);
+        assert!(!report.truncated_by_pruning);
+        assert_eq!((report.oldest_slot, report.newest_slot), (8, 10));
+    }
+
+    fn start_time() -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000, 0).unwrap()
+    }
+
+    #[test]
+    fn calculation_duration_comes_from_the_clock() {
+        let source = source(chain(11, 1_000));
+        let clock = MockClock::new(start_time(), chrono::Duration::seconds(3));
+
+        let report = calculate_for_range_with_clock(&source, ScanWindow::Seconds(5), &ScanOptions::default(), &clock)
+            .unwrap();
+
+        //Read once when the scan starts and once when it ends
+        assert_eq!(report.calculation_duration, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn max_runtime_stops_the_scan_early() {
+        let source = source(chain(101, 1_000));
+        //Every counted block reads the clock once to check the runtime
+        let clock = MockClock::new(start_time(), chrono::Duration::seconds(1));
+        let options = ScanOptions {
+            max_runtime: Some(Duration::from_secs(4)),
+            ..ScanOptions::default()
+        };
+
+        let report = calculate_for_range_with_clock(&source, ScanWindow::Seconds(60), &options, &clock).unwrap();
+
+        assert!(report.time_limited);
+        assert!(report.window_truncated);
+        assert_eq!(report.blocks_scanned, 4);
+    }
+
+    //Source whose endpoint leaves the transactions out of the first few blocks it is asked for
+    struct StrippingSource {
+        inner: FileBlockSource,
+        stripped_responses: Cell<u32>,
+    }
+
+    impl BlockSource for StrippingSource {
+        fn get_slot(&self) -> Result<u64, TpsError> {
+            self.inner.get_slot()
+        }
+
+        fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+            self.inner.get_block(slot)
+        }
+
+        fn get_block_with_details(
+            &self,
+            slot: u64,
+            details: TransactionDetailLevel,
+            rewards: bool
+        ) -> Result<(EncodedConfirmedBlock, Option<u64>), TpsError> {
+            let (block, transaction_count) = self.inner.get_block_with_details(slot, details, rewards)?;
+            if self.stripped_responses.get() == 0 {
+                return Ok((block, transaction_count));
+            }
+            self.stripped_responses.set(self.stripped_responses.get() - 1);
+
+            Ok((block, None))
+        }
+
+        fn get_first_available_block(&self) -> Result<u64, TpsError> {
+            self.inner.get_first_available_block()
+        }
+    }
+
+    fn stripping_source(stripped_responses: u32) -> StrippingSource {
+        StrippingSource {
+            inner: source(vec![block(7, 1_000, vec![user_tx(Pubkey::new_unique())])]),
+            stripped_responses: Cell::new(stripped_responses),
+        }
+    }
+
+    #[test]
+    fn block_without_its_transactions_is_fetched_again() {
+        let source = stripping_source(1);
+
+        let (_, transaction_count) = get_block_with_details(&source, 7, TransactionDetailLevel::Full, true).unwrap();
+
+        assert_eq!(transaction_count, Some(1));
+        assert_eq!(source.stripped_responses.get(), 0);
+    }
+
+    #[test]
+    fn block_without_its_transactions_twice_fails_the_scan() {
+        let result = get_block_with_details(&stripping_source(2), 7, TransactionDetailLevel::Full, true);
+
+        assert!(matches!(result, Err(TpsError::DetailStripped(7))));
+    }
+
+    #[test]
+    fn block_fetched_without_transactions_is_not_fetched_again() {
+        let source = stripping_source(1);
+
+        let (_, transaction_count) = get_block_with_details(&source, 7, TransactionDetailLevel::None, true).unwrap();
+
+        assert_eq!(transaction_count, None);
+        assert_eq!(source.stripped_responses.get(), 0);
+    }
+
+    #[test]
+    fn report_counts_reward_only_blocks_in_the_window() {
+        let mut blocks = chain(11, 1_000);
+        for block in blocks.iter_mut().step_by(2) {
+            block.rewards = vec![Reward {
+                pubkey: Pubkey::new_unique().to_string(),
+                lamports: 5_000,
+                post_balance: 1_000_000,
+                reward_type: None,
+                commission: None,
+            }];
+        }
+
+        let report = calculate_for_range(&source(blocks), ScanWindow::Seconds(4), &ScanOptions::default()).unwrap();
+
+        //Slots 10 and 8 of the counted 7 to 10 paid out rewards without transactions
+        assert_eq!(report.oldest_slot, 7);
+        assert_eq!(report.reward_only_blocks, 2);
+    }
+
+    #[test]
+    fn produced_slot_share_leaves_out_skipped_slots() {
+        //Every third slot of the sampled range was skipped by its leader
+        let blocks = chain(1_000 + SKIP_RATE_SAMPLE_SLOTS, 0)
+            .into_iter()
+            .skip(1_000)
+            .filter(|block| block.block_height.unwrap() % 3 != 0)
+            .collect();
+        let source = source(blocks);
+        let newest_slot = source.get_slot().unwrap();
+
+        let share = produced_slot_share(&source, newest_slot).unwrap();
+
+        assert!((share - 2.0 / 3.0).abs() < 0.01, "share {}", share);
+    }
+
+    #[test]
+    fn produced_slot_share_near_genesis_only_samples_existing_slots() {
+        let source = source(chain(10, 0));
+
+        assert_eq!(produced_slot_share(&source, 9).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn slot_time_drift_is_measured_over_the_counted_slots() {
+        //One block per second is 2.5 times the nominal slot time
+        let report = calculate_for_range(&source(chain(41, 1_000)), ScanWindow::LastBlocks(30), &ScanOptions::default())
+            .unwrap();
+
+        assert_eq!(report.counted_slots, 30);
+        assert!((report.slot_time_drift.unwrap() - 1.5).abs() < 1e-9, "{:?}", report.slot_time_drift);
+    }
+
+    #[test]
+    fn single_block_window_has_drift_over_the_slots_since_its_parent() {
+        //The only counted block comes 30 slots after its parent, right on the nominal slot time
+        let mut blocks = chain(31, 1_000);
+        blocks.retain(|block| matches!(block.block_height, Some(0) | Some(30)));
+        blocks[1].parent_slot = 0;
+        blocks[1].block_time = Some(1_012);
+
+        let report = calculate_for_range(&source(blocks), ScanWindow::LastBlocks(1), &ScanOptions::default()).unwrap();
+
+        assert_eq!((report.oldest_slot, report.newest_slot, report.counted_slots), (30, 30, 30));
+        assert!(report.slot_time_drift.unwrap().abs() < 1e-9, "{:?}", report.slot_time_drift);
+    }
+
+    #[test]
+    fn oldest_timestamp_comes_from_the_boundary_block() {
+        let report = calculate_for_range(&source(chain(41, 1_000)), ScanWindow::LastBlocks(10), &ScanOptions::default())
+            .unwrap();
+
+        assert_eq!((report.oldest_slot, report.boundary_slot), (31, 30));
+        assert_eq!(report.oldest_timestamp, 1_030);
+
+        //Without the pruned parent, the oldest block the walk reached is the boundary
+        let pruned = source(chain(61, 1_000).into_iter().skip(50).collect());
+        let report = calculate_for_range(&pruned, ScanWindow::LastBlocks(20), &ScanOptions::default()).unwrap();
+
+        assert!(report.truncated_by_pruning);
+        assert_eq!((report.oldest_slot, report.boundary_slot), (51, 50));
+        assert_eq!(report.oldest_timestamp, 1_050);
+    }
+
+    #[test]
+    fn time_window_walks_the_listed_blocks_instead_of_parent_slots() {
+        //Slot 15 was skipped, but the block after it still names it as its parent
+        let mut blocks = chain(21, 1_000);
+        blocks.remove(15);
+
+        let report = calculate_for_range(&source(blocks), ScanWindow::Seconds(10), &ScanOptions::default()).unwrap();
+
+        assert!(!report.window_truncated);
+        assert_eq!((report.oldest_slot, report.newest_slot), (11, 20));
+        assert_eq!(report.blocks_scanned, 9);
+    }
+
+    #[test]
+    fn window_start_is_found_by_block_time() {
+        //Every fourth slot was skipped, so slot numbers and block times drift apart
+        let blocks: Vec<EncodedConfirmedBlock> = chain(20_000, 0)
+            .into_iter()
+            .filter(|block| block.block_height.unwrap() % 4 != 0)
+            .collect();
+
+        let start_slot = window_start_slot(&source(blocks), 19_999, 15_000, 0).unwrap();
+
+        assert!((15_000 - MAX_TIP_SEARCH_SLOTS..=15_000).contains(&start_slot), "start slot {}", start_slot);
+    }
+
+    #[test]
+    fn window_start_stops_at_the_oldest_slot() {
+        let source = source(chain(61, 1_000).into_iter().skip(50).collect());
+
+        assert_eq!(window_start_slot(&source, 60, 900, 50).unwrap(), 50);
+    }
+
+    #[test]
+    fn blocks_after_a_slot_leave_out_the_tip_lag_and_warmup() {
+        let source = source(chain(21, 1_000));
+        let options = |tip_lag, warmup_blocks| ScanOptions {
+            tip_lag,
+            warmup_blocks,
+            ..ScanOptions::default()
+        };
+
+        assert!(has_blocks_after(&source, 15, &options(0, 0)).unwrap());
+        assert!(!has_blocks_after(&source, 15, &options(5, 0)).unwrap());
+        //Slots 16 and 17 are within the lag, and both are passed over as warmup
+        assert!(!has_blocks_after(&source, 15, &options(3, 2)).unwrap());
+        assert!(has_blocks_after(&source, 15, &options(3, 1)).unwrap());
+    }
+
+    #[test]
+    fn blocks_after_a_slot_are_counted_by_produced_slot() {
+        let mut blocks = chain(16, 1_000);
+        blocks.extend([block(17, 1_017, Vec::new()), block(20, 1_020, Vec::new())]);
+        blocks[16].parent_slot = 15;
+        blocks[17].parent_slot = 17;
+        let source = source(blocks);
+        let options = ScanOptions {
+            warmup_blocks: 2,
+            ..ScanOptions::default()
+        };
+
+        assert!(!has_blocks_after(&source, 15, &options).unwrap());
+        //The scan would otherwise find its start slot newer than its newest block
+        assert!(matches!(
+            calculate_for_range(&source, ScanWindow::SinceSlot(16), &options),
+            Err(TpsError::InvalidWindow(_))
+        ));
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::{ Arc, RwLock };
+use std::time::{ Duration, Instant };
+
+use async_trait::async_trait;
+use reqwest::header::{ self, HeaderMap, HeaderValue, CONTENT_TYPE, RETRY_AFTER };
+use reqwest::StatusCode;
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_request::{ RpcError, RpcRequest, RpcResponseErrorData };
+use solana_client::rpc_sender::{ RpcSender, RpcTransportStats };
+use tokio::sync::Semaphore;
+
+//Same timeout the default RPC client uses
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+//How many times a rate limited request is retried before giving up
+const TOO_MANY_REQUESTS_RETRIES: usize = 5;
+
+//Function that returns the user agent sent when none is configured
+pub fn default_user_agent() -> String {
+    format!("solana-rs/{}", env!("CARGO_PKG_VERSION"))
+}
+
+//HTTP transport for RpcClient that identifies itself with a configurable User-Agent header.
+//The default sender of the RPC client has no way to set extra headers
+pub struct UserAgentSender {
+    client: reqwest::Client,
+    url: String,
+    request_id: AtomicU64,
+    stats: RwLock<RpcTransportStats>,
+    //Shared by every sender of a run, so the cap holds across endpoints and whatever is making the requests
+    concurrency_limit: Option<Arc<Semaphore>>,
+}
+
+impl UserAgentSender {
+    //Returns None when the user agent can't be sent as a header value or the HTTP client can't be built,
+    //in which case the caller should fall back to the default sender
+    pub fn new(url: &str, user_agent: &str) -> Option<Self> {
+        let user_agent = match HeaderValue::from_str(user_agent) {
+            Ok(user_agent) => user_agent,
+            Err(err) => {
+                log::warn!("User agent {:?} is not a valid header value: {}", user_agent, err);
+                return None;
+            }
+        };
+
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(header::USER_AGENT, user_agent);
+
+        let client = reqwest::Client::builder()
+            .default_headers(default_headers)
+            .timeout(REQUEST_TIMEOUT)
+            .pool_idle_timeout(REQUEST_TIMEOUT)
+            .build();
+
+        match client {
+            Ok(client) =>
+                Some(UserAgentSender {
+                    client,
+                    url: url.to_string(),
+                    request_id: AtomicU64::new(0),
+                    stats: RwLock::new(RpcTransportStats::default()),
+                    concurrency_limit: None,
+                }),
+            Err(err) => {
+                log::warn!("Could not build an HTTP client with a custom user agent: {}", err);
+                None
+            }
+        }
+    }
+
+    //Function that makes every request wait for a permit of the given semaphore, and hold it until it is answered
+    pub fn with_concurrency_limit(mut self, concurrency_limit: Arc<Semaphore>) -> Self {
+        self.concurrency_limit = Some(concurrency_limit);
+        self
+    }
+}
+
+#[async_trait]
+impl RpcSender for UserAgentSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value
+    ) -> ClientResult<serde_json::Value> {
+        let _permit = match &self.concurrency_limit {
+            Some(concurrency_limit) => Some(concurrency_limit.acquire().await.expect("semaphore is never closed")),
+            None => None,
+        };
+
+        let request_start = Instant::now();
+        let mut rate_limited_time = Duration::ZERO;
+
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let request_json = request.build_request_json(request_id, params).to_string();
+
+        let mut retries_left = TOO_MANY_REQUESTS_RETRIES;
+        let result = loop {
+            let response = self.client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(request_json.clone())
+                .send().await;
+
+            let response = match response {
+                Ok(response) => response,
+                Err(err) => {
+                    break Err(err.into());
+                }
+            };
+
+            if response.status() == StatusCode::TOO_MANY_REQUESTS && retries_left > 0 {
+                let pause = response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|retry_after| retry_after.to_str().ok())
+                    .and_then(|retry_after| retry_after.parse::<u64>().ok())
+                    .filter(|retry_after| *retry_after < 120)
+                    .map(Duration::from_secs)
+                    .unwrap_or(Duration::from_millis(500));
+
+                retries_left -= 1;
+                log::debug!("Too many requests, {} retries left, pausing for {:?}", retries_left, pause);
+
+                tokio::time::sleep(pause).await;
+                rate_limited_time += pause;
+                continue;
+            }
+
+            if let Err(err) = response.error_for_status_ref() {
+                break Err(err.into());
+            }
+
+            let mut json = match response.json::<serde_json::Value>().await {
+                Ok(json) => json,
+                Err(err) => {
+                    break Err(err.into());
+                }
+            };
+
+            if json["error"].is_object() {
+                let error = &json["error"];
+                break match (error["code"].as_i64(), error["message"].as_str()) {
+                    (Some(code), Some(message)) =>
+                        Err(
+                            (RpcError::RpcResponseError {
+                                code,
+                                message: message.to_string(),
+                                data: RpcResponseErrorData::Empty,
+                            }).into()
+                        ),
+                    _ =>
+                        Err(
+                            RpcError::RpcRequestError(
+                                format!("Failed to deserialize RPC error response: {}", error)
+                            ).into()
+                        ),
+                };
+            }
+
+            break Ok(json["result"].take());
+        };
+
+        let mut stats = self.stats.write().unwrap();
+        stats.request_count += 1;
+        stats.elapsed_time += request_start.elapsed();
+        stats.rate_limited_time += rate_limited_time;
+
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.stats.read().unwrap().clone()
+    }
+
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ BufRead, BufReader, Read, Write };
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    use solana_client::rpc_client::{ RpcClient, RpcClientConfig };
+
+    use super::*;
+
+    //Requests the endpoint is answering right now, and the most it ever answered at once
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    //Function that starts an HTTP endpoint answering every JSON-RPC request with slot 7 after a pause,
+    //counting how many requests it is answering at once, and returns its URL
+    fn slow_endpoint(in_flight: Arc<InFlight>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let in_flight = Arc::clone(&in_flight);
+                thread::spawn(move || {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    loop {
+                        let mut content_length = 0;
+                        loop {
+                            let mut line = String::new();
+                            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                                return;
+                            }
+                            if line == "\r\n" {
+                                break;
+                            }
+                            if let Some((name, value)) = line.split_once(':') {
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    content_length = value.trim().parse().unwrap();
+                                }
+                            }
+                        }
+                        let mut body = vec![0; content_length];
+                        reader.read_exact(&mut body).unwrap();
+
+                        let current = in_flight.current.fetch_add(1, Ordering::SeqCst) + 1;
+                        in_flight.max.fetch_max(current, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(50));
+                        in_flight.current.fetch_sub(1, Ordering::SeqCst);
+
+                        let body = r#"{"jsonrpc":"2.0","result":7,"id":0}"#;
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                            body.len(),
+                            body
+                        );
+                        stream.write_all(response.as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn concurrency_limit_caps_requests_in_flight_across_senders() {
+        let in_flight = Arc::new(InFlight::default());
+        let url = slow_endpoint(Arc::clone(&in_flight));
+        let concurrency_limit = Arc::new(Semaphore::new(2));
+
+        let requests: Vec<_> = (0..6)
+            .map(|_| {
+                let sender = UserAgentSender::new(&url, &default_user_agent())
+                    .unwrap()
+                    .with_concurrency_limit(Arc::clone(&concurrency_limit));
+                let client = RpcClient::new_sender(sender, RpcClientConfig::default());
+                thread::spawn(move || client.send::<u64>(RpcRequest::GetSlot, serde_json::Value::Null).unwrap())
+            })
+            .collect();
+        for request in requests {
+            assert_eq!(request.join().unwrap(), 7);
+        }
+
+        let max_in_flight = in_flight.max.load(Ordering::SeqCst);
+        assert!((1..=2).contains(&max_in_flight), "{} requests were in flight at once", max_in_flight);
+        assert_eq!(concurrency_limit.available_permits(), 2);
+    }
+}
@@ -0,0 +1,308 @@
+use chrono::{ DateTime, TimeZone, Utc };
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+use solana_client::{
+    client_error::ClientError,
+    rpc_client::RpcClient,
+    rpc_config::RpcBlockConfig,
+    rpc_custom_error,
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::{
+    EncodedConfirmedBlock,
+    TransactionDetails,
+    UiConfirmedBlock,
+    UiTransactionEncoding,
+};
+
+use crate::error::{ is_slot_unavailable, rpc_error_code, TpsError };
+
+//How much of each transaction a block is fetched with. Less detail means smaller responses and faster scans
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionDetailLevel {
+    //Every transaction with its instructions and status, needed to tell vote and user transactions apart
+    #[default]
+    Full,
+    //Only the signatures, enough to count the transactions of a block
+    Signatures,
+    //No transactions at all, only the block's slot, time and height
+    None,
+}
+
+impl From<TransactionDetailLevel> for TransactionDetails {
+    fn from(level: TransactionDetailLevel) -> Self {
+        match level {
+            TransactionDetailLevel::Full => TransactionDetails::Full,
+            TransactionDetailLevel::Signatures => TransactionDetails::Signatures,
+            TransactionDetailLevel::None => TransactionDetails::None,
+        }
+    }
+}
+
+//How settled a block has to be for the RPC endpoint to return it, and for its slot to count as the tip
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Commitment {
+    //Voted on by a supermajority of the cluster. Closer to the tip, but a block may still be dropped
+    Confirmed,
+    //Rooted, so it won't be rolled back
+    #[default]
+    Finalized,
+}
+
+impl From<Commitment> for CommitmentConfig {
+    fn from(commitment: Commitment) -> Self {
+        match commitment {
+            Commitment::Confirmed => CommitmentConfig::confirmed(),
+            Commitment::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+//Anything blocks can be read from. The scan only talks to the chain through this trait,
+//so it can be pointed at something other than a live RPC endpoint
+pub trait BlockSource {
+    //Newest slot the source knows about
+    fn get_slot(&self) -> Result<u64, TpsError>;
+
+    //Block produced in the given slot
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError>;
+
+    //Block produced in the given slot with only the given transaction detail, and its rewards only if asked for,
+    //along with its number of transactions when the detail level includes them. None at a level that includes
+    //them means the endpoint left them out. Sources that hold full blocks anyway just return those
+    fn get_block_with_details(
+        &self,
+        slot: u64,
+        _details: TransactionDetailLevel,
+        _rewards: bool
+    ) -> Result<(EncodedConfirmedBlock, Option<u64>), TpsError> {
+        let block = self.get_block(slot)?;
+        let transaction_count = block.transactions.len() as u64;
+
+        Ok((block, Some(transaction_count)))
+    }
+
+    //Block time of the block produced in the given slot, None if the block has none. Sources that can read it
+    //without the rest of the block should, as it is enough to locate a window
+    fn get_block_time(&self, slot: u64) -> Result<Option<i64>, TpsError> {
+        Ok(self.get_block(slot)?.block_time)
+    }
+
+    //Slots between start and end, both included, that have a block, in ascending order. Sources that can't list
+    //them in one go find out by asking for every block time
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        let mut slots = Vec::new();
+        for slot in start_slot..=end_slot {
+            match self.get_block_time(slot) {
+                Ok(_) => slots.push(slot),
+                Err(TpsError::NoBlockAvailable(_)) => {}
+                Err(err) => {
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(slots)
+    }
+
+    //Oldest slot the source still has a block for. Older blocks may have been pruned from its ledger
+    fn get_first_available_block(&self) -> Result<u64, TpsError>;
+}
+
+//A borrowed source reads from the source it borrows, so wrappers can be put around a source that is still
+//needed afterwards
+impl<S: BlockSource + ?Sized> BlockSource for &S {
+    fn get_slot(&self) -> Result<u64, TpsError> {
+        (**self).get_slot()
+    }
+
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+        (**self).get_block(slot)
+    }
+
+    fn get_block_with_details(
+        &self,
+        slot: u64,
+        details: TransactionDetailLevel,
+        rewards: bool
+    ) -> Result<(EncodedConfirmedBlock, Option<u64>), TpsError> {
+        (**self).get_block_with_details(slot, details, rewards)
+    }
+
+    fn get_block_time(&self, slot: u64) -> Result<Option<i64>, TpsError> {
+        (**self).get_block_time(slot)
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        (**self).get_blocks(start_slot, end_slot)
+    }
+
+    fn get_first_available_block(&self) -> Result<u64, TpsError> {
+        (**self).get_first_available_block()
+    }
+}
+
+impl BlockSource for RpcClient {
+    fn get_slot(&self) -> Result<u64, TpsError> {
+        Ok(RpcClient::get_slot(self)?)
+    }
+
+    fn get_block(&self, slot: u64) -> Result<EncodedConfirmedBlock, TpsError> {
+        let block = fetch_block(self, slot, TransactionDetailLevel::Full, true)?;
+        if block.transactions.is_none() {
+            return Err(TpsError::DetailStripped(slot));
+        }
+
+        Ok(block.into())
+    }
+
+    fn get_block_with_details(
+        &self,
+        slot: u64,
+        details: TransactionDetailLevel,
+        rewards: bool
+    ) -> Result<(EncodedConfirmedBlock, Option<u64>), TpsError> {
+        let block = fetch_block(self, slot, details, rewards)?;
+
+        let transaction_count = match details {
+            TransactionDetailLevel::Full => block.transactions.as_ref().map(Vec::len),
+            TransactionDetailLevel::Signatures => block.signatures.as_ref().map(Vec::len),
+            TransactionDetailLevel::None => None,
+        };
+
+        Ok((block.into(), transaction_count.map(|count| count as u64)))
+    }
+
+    fn get_block_time(&self, slot: u64) -> Result<Option<i64>, TpsError> {
+        match RpcClient::get_block_time(self, slot) {
+            Ok(block_time) => Ok(Some(block_time)),
+            Err(err) => Err(block_error(slot, err)),
+        }
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, TpsError> {
+        Ok(RpcClient::get_blocks(self, start_slot, Some(end_slot))?)
+    }
+
+    fn get_first_available_block(&self) -> Result<u64, TpsError> {
+        Ok(RpcClient::get_first_available_block(self)?)
+    }
+}
+
+//Sources that can tell how fast a scan over them goes: how many slots the cluster produces per second, from the
+//performance samples an RPC node keeps, and how many requests per second the source may be sent
+pub trait PerformanceSamples {
+    //Slots produced per second over the most recent performance sample, 0 when there is none
+    fn recent_slots_per_second(&self) -> Result<f64, TpsError>;
+
+    //Most requests per second the source serves, None when it isn't rate limited
+    fn max_requests_per_second(&self) -> Option<f64> {
+        None
+    }
+}
+
+impl<S: PerformanceSamples + ?Sized> PerformanceSamples for &S {
+    fn recent_slots_per_second(&self) -> Result<f64, TpsError> {
+        (**self).recent_slots_per_second()
+    }
+
+    fn max_requests_per_second(&self) -> Option<f64> {
+        (**self).max_requests_per_second()
+    }
+}
+
+impl PerformanceSamples for RpcClient {
+    fn recent_slots_per_second(&self) -> Result<f64, TpsError> {
+        let samples = self.get_recent_performance_samples(Some(1))?;
+
+        Ok(match samples.first() {
+            Some(sample) if sample.sample_period_secs > 0 => {
+                (sample.num_slots as f64) / (sample.sample_period_secs as f64)
+            }
+            _ => 0.0,
+        })
+    }
+}
+
+//Function that fetches a block from the RPC endpoint with the given transaction detail, and rewards if asked for
+fn fetch_block(
+    client: &RpcClient,
+    slot: u64,
+    details: TransactionDetailLevel,
+    rewards: bool
+) -> Result<UiConfirmedBlock, TpsError> {
+    let config = RpcBlockConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        transaction_details: Some(details.into()),
+        rewards: Some(rewards),
+        max_supported_transaction_version: Some(0),
+        commitment: Some(client.commitment()),
+    };
+
+    client.get_block_with_config(slot, config).map_err(|err| block_error(slot, err))
+}
+
+//Function that turns the error of a request about the block in a slot into the scan's error for it
+fn block_error(slot: u64, err: ClientError) -> TpsError {
+    if !is_slot_unavailable(&err) {
+        return err.into();
+    }
+
+    match rpc_error_code(&err) {
+        Some(rpc_custom_error::JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP) => TpsError::BlockCleanedUp(slot),
+        _ => TpsError::NoBlockAvailable(slot),
+    }
+}
+
+//Function that gives the time a block was produced, if the block has one that is a valid date
+pub fn block_timestamp(block: &EncodedConfirmedBlock) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(block.block_time?, 0).single()
+}
+
+//Function that copies a block. EncodedConfirmedBlock doesn't implement Clone, although all of its fields do
+pub fn clone_block(block: &EncodedConfirmedBlock) -> EncodedConfirmedBlock {
+    EncodedConfirmedBlock {
+        previous_blockhash: block.previous_blockhash.clone(),
+        blockhash: block.blockhash.clone(),
+        parent_slot: block.parent_slot,
+        transactions: block.transactions.clone(),
+        rewards: block.rewards.clone(),
+        block_time: block.block_time,
+        block_height: block.block_height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::rpc_response_error;
+
+    #[test]
+    fn cleaned_up_block_has_its_own_error() {
+        let err = rpc_response_error(
+            rpc_custom_error::JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP,
+            "Block 7 cleaned up, does not exist on node. First available block: 9"
+        );
+
+        assert!(matches!(block_error(7, err), TpsError::BlockCleanedUp(7)));
+    }
+
+    #[test]
+    fn skipped_and_missing_blocks_are_unavailable() {
+        for code in [
+            rpc_custom_error::JSON_RPC_SERVER_ERROR_SLOT_SKIPPED,
+            rpc_custom_error::JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+        ] {
+            assert!(matches!(block_error(7, rpc_response_error(code, "unavailable")), TpsError::NoBlockAvailable(7)));
+        }
+    }
+
+    #[test]
+    fn other_block_errors_stay_rpc_errors() {
+        let err = rpc_response_error(-32602, "Invalid params");
+
+        assert!(matches!(block_error(7, err), TpsError::Rpc(_)));
+    }
+}
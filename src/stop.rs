@@ -0,0 +1,160 @@
+use crate::error::TpsError;
+use crate::scan::ScanWindow;
+
+//Where the backward walk stands after counting a block. The parent is the next block the walk would count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkState {
+    pub blocks_scanned: u64,
+    pub parent_slot: u64,
+    pub parent_timestamp: i64,
+    pub parent_block_height: Option<u64>,
+}
+
+//One reason to end the backward walk, checked after every counted block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    //The parent block is at or before this timestamp, so it lies outside the time window
+    TimeWindow {
+        threshold_timestamp: i64,
+    },
+    //This many blocks have been counted
+    BlockCount(u64),
+    //The parent block is older than this slot
+    StartSlot(u64),
+    //The parent block is the genesis block, so there is nothing further back
+    GenesisReached,
+    //This many blocks have been counted, whatever the window asked for
+    BlockCap(u64),
+}
+
+impl StopCondition {
+    pub fn is_met(&self, state: &WalkState) -> bool {
+        match *self {
+            StopCondition::TimeWindow { threshold_timestamp } => {
+                state.parent_timestamp <= threshold_timestamp
+            }
+            StopCondition::BlockCount(block_count) | StopCondition::BlockCap(block_count) => {
+                state.blocks_scanned >= block_count
+            }
+            StopCondition::StartSlot(start_slot) => state.parent_slot < start_slot,
+            StopCondition::GenesisReached => state.parent_block_height == Some(0),
+        }
+    }
+
+    //Whether stopping here means the requested window was covered. The other conditions end the walk early
+    pub fn completes_window(&self) -> bool {
+        match self {
+            StopCondition::TimeWindow { .. } | StopCondition::BlockCount(_) | StopCondition::StartSlot(_) => true,
+            StopCondition::GenesisReached | StopCondition::BlockCap(_) => false,
+        }
+    }
+}
+
+//Function that returns the conditions ending a walk over the window, which starts at the newest block's timestamp.
+//Reaching genesis always ends the walk, whatever the window, and so does the block cap if there is one
+pub fn stop_conditions(
+    window: ScanWindow,
+    newest_timestamp: i64,
+    max_blocks: Option<u64>
+) -> Result<Vec<StopCondition>, TpsError> {
+    let window_condition = match window {
+        ScanWindow::Seconds(threshold_seconds) => {
+            let threshold_timestamp = newest_timestamp
+                .checked_sub(threshold_seconds)
+                .ok_or_else(|| {
+                    TpsError::InvalidWindow(format!("window of {} seconds reaches before the epoch", threshold_seconds))
+                })?;
+            StopCondition::TimeWindow { threshold_timestamp }
+        }
+        ScanWindow::LastBlocks(block_count) => StopCondition::BlockCount(block_count),
+        ScanWindow::SinceSlot(start_slot) => StopCondition::StartSlot(start_slot),
+        ScanWindow::ListedSlots(slot_count) => StopCondition::BlockCount(slot_count),
+    };
+
+    let mut conditions = vec![window_condition, StopCondition::GenesisReached];
+    if let Some(max_blocks) = max_blocks {
+        conditions.push(StopCondition::BlockCap(max_blocks));
+    }
+
+    Ok(conditions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(blocks_scanned: u64, parent_slot: u64, parent_timestamp: i64) -> WalkState {
+        WalkState {
+            blocks_scanned,
+            parent_slot,
+            parent_timestamp,
+            parent_block_height: Some(parent_slot),
+        }
+    }
+
+    #[test]
+    fn time_window_is_met_once_the_parent_reaches_the_threshold() {
+        let condition = StopCondition::TimeWindow { threshold_timestamp: 1_000 };
+
+        assert!(!condition.is_met(&state(3, 10, 1_001)));
+        assert!(condition.is_met(&state(3, 10, 1_000)));
+        assert!(condition.is_met(&state(3, 10, 999)));
+        assert!(condition.completes_window());
+    }
+
+    #[test]
+    fn block_count_is_met_once_enough_blocks_are_counted() {
+        let condition = StopCondition::BlockCount(5);
+
+        assert!(!condition.is_met(&state(4, 10, 1_000)));
+        assert!(condition.is_met(&state(5, 10, 1_000)));
+        assert!(condition.completes_window());
+    }
+
+    #[test]
+    fn start_slot_is_met_once_the_parent_is_older() {
+        let condition = StopCondition::StartSlot(10);
+
+        assert!(!condition.is_met(&state(1, 10, 1_000)));
+        assert!(condition.is_met(&state(1, 9, 1_000)));
+        assert!(condition.completes_window());
+    }
+
+    #[test]
+    fn genesis_reached_is_met_at_block_height_zero() {
+        let condition = StopCondition::GenesisReached;
+
+        assert!(!condition.is_met(&state(1, 1, 1_000)));
+        assert!(condition.is_met(&state(1, 0, 1_000)));
+        assert!(!condition.is_met(&WalkState { parent_block_height: None, ..state(1, 0, 1_000) }));
+        assert!(!condition.completes_window());
+    }
+
+    #[test]
+    fn block_cap_ends_the_walk_without_completing_the_window() {
+        let condition = StopCondition::BlockCap(2);
+
+        assert!(!condition.is_met(&state(1, 10, 1_000)));
+        assert!(condition.is_met(&state(2, 10, 1_000)));
+        assert!(!condition.completes_window());
+    }
+
+    #[test]
+    fn stop_conditions_combine_the_window_with_genesis_and_the_cap() {
+        assert_eq!(
+            stop_conditions(ScanWindow::Seconds(60), 1_060, None).unwrap(),
+            vec![StopCondition::TimeWindow { threshold_timestamp: 1_000 }, StopCondition::GenesisReached]
+        );
+        assert_eq!(
+            stop_conditions(ScanWindow::SinceSlot(7), 1_060, Some(3)).unwrap(),
+            vec![StopCondition::StartSlot(7), StopCondition::GenesisReached, StopCondition::BlockCap(3)]
+        );
+    }
+
+    #[test]
+    fn stop_conditions_reject_windows_before_the_epoch() {
+        let result = stop_conditions(ScanWindow::Seconds(i64::MAX), -10, None);
+
+        assert!(matches!(result, Err(TpsError::InvalidWindow(_))));
+    }
+}
@@ -0,0 +1,227 @@
+use std::cell::Cell;
+
+use chrono::{ DateTime, Duration, Utc };
+use solana_client::client_error::ClientError;
+use solana_client::rpc_request::{ RpcError, RpcResponseErrorData };
+use solana_sdk::{
+    hash::Hash,
+    instruction::{ Instruction, InstructionError },
+    message::{ Message, VersionedMessage },
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{ TransactionError, VersionedTransaction },
+};
+use solana_transaction_status::{
+    EncodableWithMeta,
+    EncodedConfirmedBlock,
+    EncodedTransactionWithStatusMeta,
+    TransactionStatusMeta,
+    UiTransactionEncoding,
+};
+
+use crate::clock::Clock;
+use crate::records::{ BlockRecord, FileBlockSource, BLOCK_RECORD_VERSION };
+
+//Fee, in lamports, paid by the transactions the builders make. One signature at the base fee
+pub const DEFAULT_FEE: u64 = 5000;
+
+//Function that builds a block in the given slot holding the given transactions. Its parent is the
+//previous slot and its block height equals the slot, so slot 0 is genesis
+pub fn block(
+    slot: u64,
+    block_time: i64,
+    transactions: Vec<EncodedTransactionWithStatusMeta>
+) -> EncodedConfirmedBlock {
+    EncodedConfirmedBlock {
+        previous_blockhash: Hash::new_unique().to_string(),
+        blockhash: Hash::new_unique().to_string(),
+        parent_slot: slot.saturating_sub(1),
+        transactions,
+        rewards: Vec::new(),
+        block_time: Some(block_time),
+        block_height: Some(slot),
+    }
+}
+
+//Function that builds a chain of len empty blocks from genesis on, one second apart from the given
+//start time, so the block in each slot has the block time start_time + slot
+pub fn chain(len: u64, start_time: i64) -> Vec<EncodedConfirmedBlock> {
+    (0..len)
+        .map(|slot| block(slot, start_time.saturating_add(slot as i64), Vec::new()))
+        .collect()
+}
+
+//Function that builds a transaction with one instruction per given program, paid for by a fresh
+//fee payer, encoded in Base64 like the blocks the RPC source fetches
+pub fn transaction(program_ids: &[Pubkey], meta: TransactionStatusMeta) -> EncodedTransactionWithStatusMeta {
+    let fee_payer = Pubkey::new_unique();
+    let instructions: Vec<Instruction> = program_ids
+        .iter()
+        .map(|program_id| Instruction::new_with_bytes(*program_id, &[], Vec::new()))
+        .collect();
+    let message = Message::new(&instructions, Some(&fee_payer));
+
+    let transaction = VersionedTransaction {
+        signatures: vec![Signature::default(); usize::from(message.header.num_required_signatures)],
+        message: VersionedMessage::Legacy(message),
+    };
+
+    EncodedTransactionWithStatusMeta {
+        transaction: transaction.encode_with_meta(UiTransactionEncoding::Base64, &meta),
+        meta: Some(meta.into()),
+        version: None,
+    }
+}
+
+//Function that builds a successful user transaction calling the given program
+pub fn user_tx(program_id: Pubkey) -> EncodedTransactionWithStatusMeta {
+    transaction(&[program_id], TransactionStatusMeta {
+        fee: DEFAULT_FEE,
+        ..TransactionStatusMeta::default()
+    })
+}
+
+//Function that builds a vote transaction, whose only instruction goes to the vote program
+pub fn vote_tx() -> EncodedTransactionWithStatusMeta {
+    transaction(&[solana_sdk::vote::program::id()], TransactionStatusMeta {
+        fee: DEFAULT_FEE,
+        ..TransactionStatusMeta::default()
+    })
+}
+
+//Function that builds a user transaction calling the given program that failed but still paid its fee
+pub fn failed_tx(program_id: Pubkey) -> EncodedTransactionWithStatusMeta {
+    transaction(&[program_id], TransactionStatusMeta {
+        status: Err(TransactionError::InstructionError(0, InstructionError::Custom(0))),
+        fee: DEFAULT_FEE,
+        ..TransactionStatusMeta::default()
+    })
+}
+
+//Function that builds the error a request fails with when the endpoint answers with a JSON-RPC error
+//response with the given code
+pub fn rpc_response_error(code: i64, message: &str) -> ClientError {
+    ClientError::from(RpcError::RpcResponseError {
+        code,
+        message: message.to_string(),
+        data: RpcResponseErrorData::Empty,
+    })
+}
+
+//Function that builds a block source holding the given blocks, each in the slot its block height names,
+//which the block builder sets to its slot. The newest block plays the chain tip
+pub fn source(blocks: Vec<EncodedConfirmedBlock>) -> FileBlockSource {
+    let records = blocks
+        .into_iter()
+        .map(|block| BlockRecord {
+            version: BLOCK_RECORD_VERSION,
+            slot: block.block_height.expect("built blocks have a block height"),
+            block,
+        })
+        .collect();
+
+    FileBlockSource::from_records(records).expect("built blocks are in distinct slots")
+}
+
+//Clock that starts at a fixed time and moves forward by a fixed step every time it is read,
+//so the runtime and duration a scan measures only depend on how often it looks at the clock
+pub struct MockClock {
+    now: Cell<DateTime<Utc>>,
+    step: Duration,
+}
+
+impl MockClock {
+    pub fn new(start: DateTime<Utc>, step: Duration) -> Self {
+        MockClock {
+            now: Cell::new(start),
+            step,
+        }
+    }
+
+    //Function that moves the clock forward without reading it
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        let now = self.now.get();
+        self.now.set(now + self.step);
+        now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::analysis::{ analyze_block, AnalysisOptions };
+    use crate::scan::{ calculate_for_range_with_clock, ScanOptions, ScanWindow };
+    use crate::source::BlockSource;
+
+    #[test]
+    fn built_blocks_count_votes_apart_from_user_transactions() {
+        let program_id = Pubkey::new_unique();
+        let block = block(10, 1_000, vec![user_tx(program_id), vote_tx(), failed_tx(program_id)]);
+
+        assert_eq!(block.parent_slot, 9);
+        assert_eq!(analyze_block(&block, &AnalysisOptions::default()).user_transactions, 2);
+    }
+
+    #[test]
+    fn chain_counts_block_times_from_the_start_time() {
+        let chain = chain(3, 1_000);
+        let block_times: Vec<Option<i64>> = chain.iter().map(|block| block.block_time).collect();
+
+        assert_eq!(block_times, vec![Some(1_000), Some(1_001), Some(1_002)]);
+        assert_eq!(chain[0].block_height, Some(0));
+    }
+
+    #[test]
+    fn source_holds_the_built_blocks_by_slot() {
+        let source = source(vec![block(4, 100, Vec::new()), block(5, 101, Vec::new()), block(7, 102, Vec::new())]);
+
+        assert_eq!(source.get_slot().unwrap(), 7);
+        assert_eq!(source.get_first_available_block().unwrap(), 4);
+        assert_eq!(source.get_block_time(5).unwrap(), Some(101));
+        assert_eq!(source.get_blocks(4, 7).unwrap(), vec![4, 5, 7]);
+    }
+
+    #[test]
+    fn mock_clock_steps_on_every_read() {
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let clock = MockClock::new(start, Duration::seconds(2));
+
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.now(), start + Duration::seconds(2));
+        clock.advance(Duration::seconds(10));
+        assert_eq!(clock.now(), start + Duration::seconds(14));
+    }
+
+    #[test]
+    fn scan_over_built_blocks_counts_their_user_transactions() {
+        let program_id = Pubkey::new_unique();
+        let mut blocks = chain(11, 1_000);
+        for block in &mut blocks {
+            block.transactions = vec![user_tx(program_id), vote_tx()];
+        }
+        let source = source(blocks);
+        let clock = MockClock::new(Utc.timestamp_opt(1_700_000_000, 0).unwrap(), Duration::zero());
+
+        let report = calculate_for_range_with_clock(
+            &source,
+            ScanWindow::Seconds(5),
+            &ScanOptions::default(),
+            &clock
+        ).unwrap();
+
+        //Slots 6 to 10 each add a second from their parent, which fills the window
+        assert_eq!((report.oldest_slot, report.newest_slot), (6, 10));
+        assert_eq!(report.blocks_scanned, 5);
+        assert_eq!(report.user_transactions, 5);
+        assert_eq!(report.vote_transactions, 5);
+        assert_eq!(report.transactions_per_second, 1.0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+
+use tracing::span::{ Attributes, Id };
+use tracing::Subscriber;
+use tracing_subscriber::layer::{ Context, Layer };
+use tracing_subscriber::registry::LookupSpan;
+
+//Total time spent inside spans, keyed by span name
+pub type PhaseTotals = Arc<Mutex<BTreeMap<&'static str, Duration>>>;
+
+//Function that formats a duration the way a person would write it: microseconds or milliseconds under a second,
+//seconds with two decimals under a minute, and whole minutes and seconds beyond, e.g. "340ms", "1.24s", "2m 13s"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    match seconds {
+        0 if duration < Duration::from_millis(1) => format!("{}µs", duration.as_micros()),
+        0 => format!("{}ms", duration.as_millis()),
+        //Truncated like the other units, as rounding would turn 59.999s into 60.00s
+        1..=59 => format!("{}.{:02}s", seconds, duration.subsec_millis() / 10),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m {}s", seconds / 3600, (seconds % 3600) / 60, seconds % 60),
+    }
+}
+
+//Time at which a span was last entered, stored in the span's extensions
+struct EnteredAt(Option<Instant>);
+
+//Tracing layer that adds up how long each named span was entered for
+pub struct TimingLayer {
+    totals: PhaseTotals,
+}
+
+impl TimingLayer {
+    pub fn new() -> (Self, PhaseTotals) {
+        let totals = PhaseTotals::default();
+        (TimingLayer { totals: totals.clone() }, totals)
+    }
+}
+
+impl<S> Layer<S> for TimingLayer where S: Subscriber + for<'a> LookupSpan<'a> {
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(EnteredAt(None));
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(entered_at) = span.extensions_mut().get_mut::<EnteredAt>() {
+                entered_at.0 = Some(Instant::now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let elapsed = span
+                .extensions_mut()
+                .get_mut::<EnteredAt>()
+                .and_then(|entered_at| entered_at.0.take())
+                .map(|instant| instant.elapsed());
+
+            if let Some(elapsed) = elapsed {
+                let mut totals = self.totals.lock().unwrap();
+                *totals.entry(span.name()).or_default() += elapsed;
+            }
+        }
+    }
+}
+
+//Function that logs the time spent in each phase
+pub fn log_phase_totals(totals: &PhaseTotals) {
+    for (phase, duration) in totals.lock().unwrap().iter() {
+        log::info!("Time in {}: {}", phase, format_duration(*duration));
+    }
+}
+
+//The scan's spans and the phase each of them is reported as in a profile
+const PROFILE_PHASES: [(&str, &str); 3] = [("get_block", "fetch"), ("decode", "decode"), ("count", "analyze")];
+
+//Function that formats the time spent fetching, decoding and analyzing as a single line,
+//e.g. "fetch: 12.30s, decode: 4.10s, analyze: 800ms"
+pub fn format_profile(totals: &PhaseTotals) -> String {
+    let totals = totals.lock().unwrap();
+
+    PROFILE_PHASES.iter()
+        .map(|(span_name, phase)| {
+            let duration = totals.get(span_name).copied().unwrap_or_default();
+            format!("{}: {}", phase, format_duration(duration))
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_durations_are_formatted_below_a_second() {
+        assert_eq!(format_duration(Duration::ZERO), "0µs");
+        assert_eq!(format_duration(Duration::from_micros(850)), "850µs");
+        assert_eq!(format_duration(Duration::from_millis(1)), "1ms");
+        assert_eq!(format_duration(Duration::from_micros(340_900)), "340ms");
+    }
+
+    #[test]
+    fn seconds_have_two_decimals_under_a_minute() {
+        assert_eq!(format_duration(Duration::from_secs(1)), "1.00s");
+        assert_eq!(format_duration(Duration::from_millis(1_240)), "1.24s");
+        assert_eq!(format_duration(Duration::from_millis(59_999)), "59.99s");
+    }
+
+    #[test]
+    fn long_durations_are_formatted_in_minutes_and_hours() {
+        assert_eq!(format_duration(Duration::from_secs(60)), "1m 0s");
+        assert_eq!(format_duration(Duration::from_secs(133)), "2m 13s");
+        assert_eq!(format_duration(Duration::from_secs(3_600)), "1h 0m 0s");
+        assert_eq!(format_duration(Duration::from_secs(90_061)), "25h 1m 1s");
+    }
+}